@@ -0,0 +1,482 @@
+//! Reusable analysis primitives built on top of [`CollecTor`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeBounds;
+
+use chrono::{Date, DateTime, Duration, TimeZone, Utc};
+use futures::stream::{StreamExt, TryStreamExt};
+
+use crate::descriptor::kind::{BridgeExtraInfo, History};
+use crate::descriptor::{Descriptor, Fingerprint, Type};
+use crate::error::Error;
+use crate::index::File;
+use crate::CollecTor;
+
+/// Keep descriptors and errors reading a file, dropping descriptors which failed to decode.
+fn skip_decode_errors(d: Result<Descriptor, (File, Error)>) -> Option<Result<Descriptor, Error>> {
+    match d {
+        Ok(d) => Some(Ok(d)),
+        // errors reading a file are in the context of that file, unlike errors decoding a single
+        // descriptor
+        Err((_, e @ Error::InFile { .. })) => Some(Err(e)),
+        Err(_) => None,
+    }
+}
+
+/// Returns the pool assignment of bridges whose assignment did not change over `range`.
+///
+/// Descriptors which fail to decode are ignored, but files which can't be read, such as files
+/// not downloaded yet, cause an error.
+pub async fn stable_assignment<R: RangeBounds<DateTime<Utc>> + 'static>(
+    collector: &CollecTor,
+    range: R,
+) -> Result<HashMap<Fingerprint, String>, Error> {
+    let res = collector
+        .stream_descriptors(Type::BridgePoolAssignment, range)
+        .filter_map(|d| async { skip_decode_errors(d) })
+        .try_filter_map(|d| async move { Ok(d.bridge_pool_assignment().ok()) })
+        .try_fold(
+            HashMap::<Fingerprint, Option<String>>::new(),
+            |mut acc, bpa| async {
+                for (fp, assign) in bpa.data {
+                    acc.entry(fp)
+                        .and_modify(|current_assign| match current_assign {
+                            Some(ass) if *ass == assign.0 => (),
+                            _ => *current_assign = None,
+                        })
+                        .or_insert_with(|| Some(assign.0.clone()));
+                }
+                Ok(acc)
+            },
+        )
+        .await?;
+    Ok(res
+        .into_iter()
+        .filter_map(|(k, v)| v.map(|v| (k, v)))
+        .collect())
+}
+
+/// Returns the number of users from `country_code`, per day and bridge fingerprint. When a bridge
+/// published multiple reports on the same day, the highest count is kept.
+///
+/// Descriptors which fail to decode are ignored, but files which can't be read, such as files
+/// not downloaded yet, cause an error.
+pub async fn bridge_usage_by_country<R: RangeBounds<DateTime<Utc>> + 'static>(
+    collector: &CollecTor,
+    range: R,
+    country_code: &str,
+) -> Result<BTreeMap<Date<Utc>, HashMap<String, u64>>, Error> {
+    collector
+        .stream_descriptors(Type::BridgeExtraInfo, range)
+        .filter_map(|d| async { skip_decode_errors(d) })
+        .try_filter_map(|d| async move { Ok(d.bridge_extra_info().ok()) })
+        .map_ok(|d| {
+            let fp = d.fingerprint.clone();
+            let time = d.timestamp.date();
+            // counts are rounded up to a multiple of 8, use the middle of the interval
+            let usage = d
+                .bridge_ips
+                .unwrap_or_default()
+                .get(country_code)
+                .map(|c| c.saturating_sub(4))
+                .unwrap_or_default();
+            (time, fp, usage)
+        })
+        .try_fold(
+            BTreeMap::<Date<Utc>, HashMap<String, u64>>::new(),
+            |mut acc, (time, fp, usage)| async move {
+                acc.entry(time)
+                    .or_default()
+                    .entry(fp)
+                    .and_modify(|u| *u = (*u).max(usage))
+                    .or_insert(usage);
+                Ok(acc)
+            },
+        )
+        .await
+}
+
+/// Returns the number of users from `country_code` over all bridges, per day, as computed by
+/// [`bridge_usage_by_country`]. Sudden changes in this series usually indicate censorship
+/// events.
+///
+/// Descriptors which fail to decode are ignored, but files which can't be read, such as files
+/// not downloaded yet, cause an error.
+pub async fn country_usage_time_series<R: RangeBounds<DateTime<Utc>> + 'static>(
+    collector: &CollecTor,
+    country_code: &str,
+    range: R,
+) -> Result<Vec<(Date<Utc>, u64)>, Error> {
+    Ok(bridge_usage_by_country(collector, range, country_code)
+        .await?
+        .into_iter()
+        .map(|(date, usage)| (date, usage.values().sum()))
+        .collect())
+}
+
+/// Split per-bridge usage, as returned by [`bridge_usage_by_country`], by distribution
+/// mechanism according to `assignment`, as returned by [`stable_assignment`]. Bridges without
+/// a known assignment are ignored.
+pub fn split_by_distribution(
+    usage: BTreeMap<Date<Utc>, HashMap<String, u64>>,
+    assignment: &HashMap<Fingerprint, String>,
+) -> BTreeMap<Date<Utc>, HashMap<String, u64>> {
+    usage
+        .into_iter()
+        .map(|(date, usage)| {
+            let mut by_distribution = HashMap::new();
+            for (fp, usage) in usage {
+                if let Some(distribution) = fp.parse().ok().and_then(|fp| assignment.get(&fp)) {
+                    *by_distribution.entry(distribution.clone()).or_default() += usage;
+                }
+            }
+            (date, by_distribution)
+        })
+        .collect()
+}
+
+/// How often a bridge was found reachable by bridgestrap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BridgeUptimeSummary {
+    /// Measurements which found the bridge reachable
+    pub reachable: u64,
+    /// All measurements of the bridge
+    pub total: u64,
+    /// `reachable / total`, NaN if there was no measurement
+    pub fraction: f64,
+}
+
+impl BridgeUptimeSummary {
+    fn new(reachable: u64, total: u64) -> Self {
+        BridgeUptimeSummary {
+            reachable,
+            total,
+            fraction: if total == 0 {
+                f64::NAN
+            } else {
+                reachable as f64 / total as f64
+            },
+        }
+    }
+}
+
+/// Compute the fraction of bridgestrap measurements over `range` which found the bridge with
+/// `fingerprint` reachable. The fingerprint is compared ignoring case.
+///
+/// Descriptors which fail to decode are ignored, but files which can't be read, such as files
+/// not downloaded yet, cause an error.
+pub async fn bridge_uptime_fraction<R: RangeBounds<DateTime<Utc>> + 'static>(
+    collector: &CollecTor,
+    fingerprint: &str,
+    range: R,
+) -> Result<BridgeUptimeSummary, Error> {
+    let (reachable, total) = collector
+        .stream_descriptors(Type::BridgestrapStats, range)
+        .filter_map(|d| async { skip_decode_errors(d) })
+        .try_filter_map(|d| async move { Ok(d.bridgestrap_stats().ok()) })
+        .try_fold((0, 0), |(reachable, total), stats| async move {
+            Ok(stats
+                .stats
+                .iter()
+                .filter(|stat| stat.fingerprint_matches(fingerprint))
+                .fold((reachable, total), |(reachable, total), stat| {
+                    (reachable + stat.is_reachable as u64, total + 1)
+                }))
+        })
+        .await?;
+    Ok(BridgeUptimeSummary::new(reachable, total))
+}
+
+/// Values over time, in chronological order.
+pub type TimeSeries = Vec<(DateTime<Utc>, u64)>;
+
+/// Merge the bandwidth histories of `descriptors` into `(write, read)` time series, with one
+/// point per history interval, identified by the end of that interval. When several descriptors
+/// cover the same interval, the value from the most recently published one is kept.
+///
+/// Descriptors are expected to come from a single bridge.
+pub fn aggregate_bandwidth_history<'a>(
+    descriptors: impl IntoIterator<Item = &'a BridgeExtraInfo>,
+) -> (TimeSeries, TimeSeries) {
+    let mut descriptors: Vec<_> = descriptors.into_iter().collect();
+    // stable sort: for identical timestamps, the last descriptor given wins
+    descriptors.sort_by_key(|desc| desc.timestamp);
+
+    let merge = |history: fn(&BridgeExtraInfo) -> Option<&History>| {
+        descriptors
+            .iter()
+            .filter_map(|desc| history(desc))
+            .flat_map(History::samples)
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect()
+    };
+    (
+        merge(|desc| desc.write_history.as_ref()),
+        merge(|desc| desc.read_history.as_ref()),
+    )
+}
+
+/// Turnover of relays between a period and the one preceding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChurnStats {
+    pub period_start: DateTime<Utc>,
+    /// Relays present in this period, but not in the previous one
+    pub appeared: usize,
+    /// Relays present in the previous period, but not in this one
+    pub disappeared: usize,
+    /// Relays present in both periods
+    pub persistent: usize,
+}
+
+/// Measure how relays join and leave the network, by comparing the set of fingerprints seen
+/// during consecutive periods of length `window`.
+///
+/// Supports [`Type::ServerDescriptor`], [`Type::BridgeServerDescriptor`] and
+/// [`Type::BridgeNetworkStatus`]; other types yield no statistics. Descriptors which fail to
+/// decode are ignored, but files which can't be read, such as files not downloaded yet, cause an
+/// error.
+pub async fn relay_churn<R: RangeBounds<DateTime<Utc>> + 'static>(
+    collector: &CollecTor,
+    ttype: Type,
+    window: Duration,
+    range: R,
+) -> Result<Vec<ChurnStats>, Error> {
+    let window = window.num_seconds().max(1);
+    let periods = collector
+        .stream_descriptors(ttype, range)
+        .filter_map(|d| async { skip_decode_errors(d) })
+        .try_fold(
+            BTreeMap::<DateTime<Utc>, HashSet<String>>::new(),
+            |mut acc, desc| async move {
+                let (timestamp, fingerprints) = match desc {
+                    Descriptor::ServerDescriptor(d) => (d.timestamp, vec![d.fingerprint]),
+                    Descriptor::BridgeServerDescriptor(d) => (d.timestamp, vec![d.fingerprint]),
+                    Descriptor::BridgeNetworkStatus(d) => (
+                        d.header.published_timestamp,
+                        d.network_status.into_iter().map(|ns| ns.identity).collect(),
+                    ),
+                    _ => return Ok(acc),
+                };
+                let period_start = Utc.timestamp(timestamp.timestamp() / window * window, 0);
+                acc.entry(period_start).or_default().extend(fingerprints);
+                Ok(acc)
+            },
+        )
+        .await?;
+    Ok(churn_between_periods(periods))
+}
+
+fn churn_between_periods(periods: BTreeMap<DateTime<Utc>, HashSet<String>>) -> Vec<ChurnStats> {
+    periods
+        .iter()
+        .zip(periods.iter().skip(1))
+        .map(|((_, previous), (period_start, current))| ChurnStats {
+            period_start: *period_start,
+            appeared: current.difference(previous).count(),
+            disappeared: previous.difference(current).count(),
+            persistent: current.intersection(previous).count(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uptime_summary() {
+        assert_eq!(
+            BridgeUptimeSummary::new(3, 4),
+            BridgeUptimeSummary {
+                reachable: 3,
+                total: 4,
+                fraction: 0.75,
+            }
+        );
+        assert!(BridgeUptimeSummary::new(0, 0).fraction.is_nan());
+    }
+
+    #[tokio::test]
+    async fn test_bridge_uptime_fraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = r#"{
+  "index_created": "2023-01-15 00:00",
+  "path": "https://collector.torproject.org",
+  "directories": [{
+    "path": "recent",
+    "directories": [{
+      "path": "bridgestrap",
+      "files": [{
+        "path": "2023-01-14-22-15-03-bridgestrap-stats",
+        "size": 559,
+        "last_modified": "2023-01-14 22:30",
+        "types": ["bridgestrap-stats 1.0"],
+        "first_published": "2023-01-14 22:15",
+        "last_published": "2023-01-14 22:15",
+        "sha256": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+      }]
+    }]
+  }]
+}"#;
+        tokio::fs::write(dir.path().join("index.json"), index)
+            .await
+            .unwrap();
+        let collector = CollecTor::new_with_url(dir.path(), None).await.unwrap();
+        let fingerprint = "005fd4d7decbb250055b861579e6fdc79ad17bee";
+
+        // the file wasn't downloaded
+        assert!(bridge_uptime_fraction(&collector, fingerprint, ..)
+            .await
+            .is_err());
+
+        let path = dir
+            .path()
+            .join("recent/bridgestrap/2023-01-14-22-15-03-bridgestrap-stats");
+        tokio::fs::create_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::copy("tests/bridge_strap_stats_test", &path)
+            .await
+            .unwrap();
+        assert_eq!(
+            bridge_uptime_fraction(&collector, fingerprint, ..)
+                .await
+                .unwrap(),
+            BridgeUptimeSummary::new(1, 1)
+        );
+        assert_eq!(
+            bridge_uptime_fraction(&collector, "00782946F4C54CE1D028F21E541EF8440ECAA0EE", ..)
+                .await
+                .unwrap(),
+            BridgeUptimeSummary::new(0, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_country_usage_time_series() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = r#"{
+  "index_created": "2022-07-18 00:00",
+  "path": "https://collector.torproject.org",
+  "directories": [{
+    "path": "recent",
+    "directories": [{
+      "path": "bridge-descriptors",
+      "directories": [{
+        "path": "extra-infos",
+        "files": [{
+          "path": "2022-07-17-19-09-00-extra-infos",
+          "size": 2430,
+          "last_modified": "2022-07-17 19:30",
+          "types": ["bridge-extra-info 1.3"],
+          "first_published": "2022-07-17 18:07",
+          "last_published": "2022-07-17 18:07",
+          "sha256": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        }]
+      }]
+    }]
+  }]
+}"#;
+        tokio::fs::write(dir.path().join("index.json"), index)
+            .await
+            .unwrap();
+        let collector = CollecTor::new_with_url(dir.path(), None).await.unwrap();
+
+        // the file wasn't downloaded
+        assert!(country_usage_time_series(&collector, "ru", ..)
+            .await
+            .is_err());
+
+        let path = dir
+            .path()
+            .join("recent/bridge-descriptors/extra-infos/2022-07-17-19-09-00-extra-infos");
+        tokio::fs::create_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::copy("tests/bridge_extra_info_test", &path)
+            .await
+            .unwrap();
+        assert_eq!(
+            country_usage_time_series(&collector, "ru", ..)
+                .await
+                .unwrap(),
+            [(Utc.ymd(2022, 7, 17), 60)]
+        );
+    }
+
+    #[test]
+    fn test_split_by_distribution() {
+        let moat_fp = "005FD4D7DECBB250055B861579E6FDC79AD17BEE";
+        let email_fp = "0121C5B1E8D1BE3C4B1F5B0C8D0E24B43C0E5A1F";
+        let assignment = HashMap::from([
+            (moat_fp.parse().unwrap(), "moat".to_owned()),
+            (email_fp.parse().unwrap(), "email".to_owned()),
+        ]);
+        let day = Utc.ymd(2022, 10, 1);
+        let usage = BTreeMap::from([(
+            day,
+            HashMap::from([
+                (moat_fp.to_owned(), 20),
+                (email_fp.to_lowercase(), 4),
+                ("unassigned".to_owned(), 100),
+            ]),
+        )]);
+
+        let split = split_by_distribution(usage, &assignment);
+        assert_eq!(split[&day]["moat"], 20);
+        assert_eq!(split[&day]["email"], 4);
+        assert_eq!(split[&day].len(), 2);
+    }
+
+    #[test]
+    fn test_aggregate_bandwidth_history() {
+        let hour = |h| Utc.ymd(2022, 1, 1).and_hms(h, 0, 0);
+        let desc = |published, history_end, data: &[u64]| {
+            let mut desc = BridgeExtraInfo::empty(published);
+            desc.write_history = Some(History {
+                timestamp: history_end,
+                duration: 3600,
+                data: data.to_vec(),
+            });
+            desc
+        };
+        let newer = desc(hour(12), hour(11), &[30, 40]);
+        let older = desc(hour(10), hour(10), &[10, 20]);
+
+        let (write, read) = aggregate_bandwidth_history([&newer, &older]);
+        assert_eq!(write, [(hour(9), 10), (hour(10), 30), (hour(11), 40)]);
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn test_churn_between_periods() {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        let set = |fps: &[&str]| fps.iter().map(|fp| fp.to_string()).collect::<HashSet<_>>();
+        let periods = [
+            (day(1), set(&["a", "b", "c"])),
+            (day(2), set(&["b", "c", "d"])),
+            (day(3), set(&["d"])),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            churn_between_periods(periods),
+            [
+                ChurnStats {
+                    period_start: day(2),
+                    appeared: 1,
+                    disappeared: 1,
+                    persistent: 2,
+                },
+                ChurnStats {
+                    period_start: day(3),
+                    appeared: 0,
+                    disappeared: 2,
+                    persistent: 1,
+                },
+            ]
+        );
+    }
+}
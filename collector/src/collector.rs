@@ -1,35 +1,219 @@
 use crate::error::{Error, ErrorKind};
 use crate::Index;
 
-use std::ops::RangeBounds;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::{RangeBounds, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use async_compat::CompatExt;
+use async_compression::tokio::bufread::XzDecoder;
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use rangetools::{BoundedSet, Rangetools};
+use reqwest::header::CONTENT_TYPE;
 use reqwest::{Client, StatusCode};
+use serde::Deserialize;
 use sha2::{Digest, Sha256};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::descriptor::file_reader::FileReader;
 use crate::descriptor::{Descriptor, Type};
+use crate::download_state::DownloadStateCache;
 use crate::index::File;
+use crate::io::atomic_write;
 
 const INDEX_URL: &str = "https://collector.torproject.org/index/index.json";
 
 /// Struct to interact with CollecTor data. Main entry-point of the crate
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CollecTor {
     base_path: PathBuf,
+    type_paths: BTreeMap<Type, PathBuf>,
     index_url: Option<String>,
+    /// Whether the index at `index_url` is xz-compressed, regardless of its extension
+    index_compressed: bool,
+    /// Where the index is stored, `base_path/index.json` if not set
+    index_path: Option<PathBuf>,
     index: Index,
+    download_concurrency: usize,
+    retry_max_attempts: usize,
+    download_state: Option<Arc<DownloadStateCache>>,
+}
+
+/// Configuration of a [`CollecTor`], usually loaded from a TOML file with
+/// [`CollecTorConfig::from_file`]. Missing optional fields use the same defaults as
+/// [`CollecTorBuilder`].
+///
+/// ```toml
+/// base_path = "/var/lib/collector"
+/// download_concurrency = 4
+///
+/// [type_paths]
+/// bridge-extra-info = "/srv/extra-infos"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CollecTorConfig {
+    pub base_path: PathBuf,
+    /// Url the index is downloaded from, the official CollecTor instance if not set
+    pub index_url: Option<String>,
+    /// Never access the network, ignoring `index_url`
+    #[serde(default)]
+    pub offline: bool,
+    /// Where the index is stored, `base_path/index.json` if not set
+    pub index_path: Option<PathBuf>,
+    #[serde(default)]
+    pub type_paths: BTreeMap<Type, PathBuf>,
+    pub download_concurrency: Option<usize>,
+    pub retry_max_attempts: Option<usize>,
+}
+
+impl CollecTorConfig {
+    /// Read a configuration from a TOML file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+/// Builder for a [`CollecTor`] with non-default settings.
+#[derive(Debug)]
+pub struct CollecTorBuilder {
+    base_path: PathBuf,
+    type_paths: BTreeMap<Type, PathBuf>,
+    index_url: Option<String>,
+    index_compressed: bool,
+    index_path: Option<PathBuf>,
+    download_concurrency: usize,
+    retry_max_attempts: usize,
+    download_state_path: Option<PathBuf>,
+}
+
+impl CollecTorBuilder {
+    /// Create a builder storing data in `base_path`, and downloading index from the official
+    /// CollecTor instance.
+    pub fn new<P: Into<PathBuf>>(base_path: P) -> Self {
+        CollecTorBuilder {
+            base_path: base_path.into(),
+            type_paths: BTreeMap::new(),
+            index_url: Some(INDEX_URL.to_owned()),
+            index_compressed: false,
+            index_path: None,
+            download_concurrency: num_cpus::get(),
+            retry_max_attempts: 3,
+            download_state_path: None,
+        }
+    }
+
+    /// Create a builder with all settings from `config`.
+    pub fn from_config(config: CollecTorConfig) -> Self {
+        let mut builder = CollecTorBuilder::new(config.base_path);
+        if config.offline {
+            builder = builder.index_url(None);
+        } else if let Some(index_url) = config.index_url {
+            builder = builder.index_url(Some(index_url));
+        }
+        if let Some(index_path) = config.index_path {
+            builder = builder.index_path(index_path);
+        }
+        for (ttype, path) in config.type_paths {
+            builder = builder.type_path(ttype, path);
+        }
+        if let Some(concurrency) = config.download_concurrency {
+            builder = builder.download_concurrency(concurrency);
+        }
+        if let Some(attempts) = config.retry_max_attempts {
+            builder = builder.retry_max_attempts(attempts);
+        }
+        builder
+    }
+
+    /// Set the url the index is downloaded from. If None, no network access will be made.
+    pub fn index_url(mut self, index_url: Option<String>) -> Self {
+        self.index_url = index_url;
+        self.index_compressed = false;
+        self
+    }
+
+    /// Set the url the index is downloaded from to an xz-compressed index, such as
+    /// `index.json.xz`. Urls ending in `.xz`, or served as `application/x-xz`, are detected
+    /// as compressed even when set with [`CollecTorBuilder::index_url`].
+    pub fn index_url_compressed(mut self, index_url: &str) -> Self {
+        self.index_url = Some(index_url.to_owned());
+        self.index_compressed = true;
+        self
+    }
+
+    /// Store the index in `path` instead of `index.json` in the base path.
+    ///
+    /// When no index url is set and there is no index at the default path, an index is built
+    /// from the files already present with [`Index::from_directory`], and saved for next time.
+    pub fn index_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.index_path = Some(path.into());
+        self
+    }
+
+    /// Store files containing descriptors of type `ttype` in `path` instead of the base path.
+    pub fn type_path<P: Into<PathBuf>>(mut self, ttype: Type, path: P) -> Self {
+        self.type_paths.insert(ttype, path.into());
+        self
+    }
+
+    /// Set how many files are downloaded in parallel. Defaults to the number of CPUs.
+    pub fn download_concurrency(mut self, concurrency: usize) -> Self {
+        self.download_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set how many times downloading a file is attempted before giving up. Defaults to 3.
+    pub fn retry_max_attempts(mut self, attempts: usize) -> Self {
+        self.retry_max_attempts = attempts;
+        self
+    }
+
+    /// Remember which files were verified in the json file at `path`, so they aren't hashed
+    /// again when a new instance is created. See [`DownloadStateCache`].
+    pub fn download_state_cache<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.download_state_path = Some(path.into());
+        self
+    }
+
+    pub async fn build(self) -> Result<CollecTor, Error> {
+        fs::create_dir_all(&self.base_path).await?;
+        for path in self.type_paths.values() {
+            fs::create_dir_all(path).await?;
+        }
+
+        let download_state = match self.download_state_path {
+            Some(path) => Some(Arc::new(DownloadStateCache::load(path).await?)),
+            None => None,
+        };
+
+        let mut collector = CollecTor {
+            base_path: self.base_path,
+            type_paths: self.type_paths,
+            index_url: self.index_url,
+            index_compressed: self.index_compressed,
+            index_path: self.index_path,
+            index: Index::default(),
+            download_concurrency: self.download_concurrency,
+            retry_max_attempts: self.retry_max_attempts,
+            download_state,
+        };
+
+        collector.reload_index().await?;
+
+        Ok(collector)
+    }
 }
 
 impl CollecTor {
     /// Create a new instance storing its data in `base_path`
     pub async fn new<P: Into<PathBuf>>(base_path: P) -> Result<Self, Error> {
-        Self::new_with_url(base_path, Some(INDEX_URL.to_owned())).await
+        CollecTorBuilder::new(base_path).build().await
     }
 
     /// Create a new instance storing its data in `base_path`, and downloading index from
@@ -38,18 +222,15 @@ impl CollecTor {
         base_path: P,
         index_url: Option<String>,
     ) -> Result<Self, Error> {
-        let base_path = base_path.into();
-        fs::create_dir_all(&base_path).await?;
-
-        let mut collector = CollecTor {
-            base_path,
-            index_url,
-            index: Index::default(),
-        };
-
-        collector.reload_index().await?;
+        CollecTorBuilder::new(base_path)
+            .index_url(index_url)
+            .build()
+            .await
+    }
 
-        Ok(collector)
+    /// Get a builder to create an instance with non-default settings.
+    pub fn builder<P: Into<PathBuf>>(base_path: P) -> CollecTorBuilder {
+        CollecTorBuilder::new(base_path)
     }
 
     /// Get the inner [`Index`]
@@ -57,17 +238,50 @@ impl CollecTor {
         &self.index
     }
 
-    /// Re-download the index. If offline, only re-read the file from filesystem.
+    /// Re-download the index. If offline, only re-read the file from filesystem, or rebuild it
+    /// from local files if there is none.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip(self), fields(base_path = %self.base_path.display()))
+    )]
     pub async fn reload_index(&mut self) -> Result<bool, Error> {
-        if let Some(index_url) = self.index_url.as_ref() {
-            let json = Client::new().get(index_url).send().await?.text().await?;
+        let index = if let Some(index_url) = self.index_url.as_ref() {
+            let resp = Client::new().get(index_url).send().await?;
+            let compressed = self.index_compressed
+                || index_url.ends_with(".xz")
+                || resp
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .map(|content_type| content_type == "application/x-xz")
+                    .unwrap_or(false);
+            let body = resp.bytes().await?;
+            let json = if compressed {
+                decompress_xz(&body).await?
+            } else {
+                body.to_vec()
+            };
 
-            let mut file = fs::File::create(self.base_path.join("index.json")).await?;
-            file.write_all(json.as_bytes()).await?;
+            // keep a copy on disk so the index can be used offline later
+            let mut file = fs::File::create(self.index_file_path()).await?;
+            file.write_all(&json).await?;
             file.flush().await?;
             std::mem::drop(file);
-        }
-        let index = Index::from_file(self.base_path.join("index.json")).await?;
+
+            Index::from_slice(&json)?
+        } else if self.index_path.is_some() || fs::metadata(self.index_file_path()).await.is_ok() {
+            Index::from_file(self.index_file_path()).await?
+        } else {
+            // type paths can be inside the base path, their files must only be indexed
+            // relative to the type path
+            let type_paths: Vec<_> = self.type_paths.values().cloned().collect();
+            let mut index = Index::from_directory_excluding(&self.base_path, &type_paths).await?;
+            for path in &type_paths {
+                index = Index::merge(index, Index::from_directory(path).await?);
+            }
+            // building the index requires hashing every file, don't do it on each start
+            fs::write(self.index_file_path(), index.to_vec()?).await?;
+            index
+        };
 
         if self.index == index {
             Ok(false)
@@ -77,39 +291,79 @@ impl CollecTor {
         }
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip(self, time_range, client))
+    )]
     pub async fn download_descriptors<R: RangeBounds<DateTime<Utc>>>(
         &self,
         descriptor_types: &[Type],
         time_range: R,
         client: Option<Client>,
+    ) -> Result<(), Vec<(Error, File)>> {
+        let files = self.index.files.iter().filter(|file| {
+            descriptor_types
+                .iter()
+                .any(|ttype| file.type_matches(ttype))
+                && file.overlap(&time_range)
+        });
+        self.download_files(files, client).await
+    }
+
+    /// Download files containing descriptors of any of `descriptor_types` which were modified
+    /// after `since`, typically the time of the previous sync.
+    ///
+    /// Unlike [`CollecTor::download_descriptors`], older files are not checked at all, so a
+    /// corrupted or missing older file goes unnoticed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip(self, client))
+    )]
+    pub async fn download_new_since(
+        &self,
+        descriptor_types: &[Type],
+        since: DateTime<Utc>,
+        client: Option<Client>,
+    ) -> Result<(), Vec<(Error, File)>> {
+        let files = self.index.files.iter().filter(|file| {
+            descriptor_types
+                .iter()
+                .any(|ttype| file.type_matches(ttype))
+                && file.last_modified > since
+        });
+        self.download_files(files, client).await
+    }
+
+    async fn download_files<'a>(
+        &'a self,
+        files: impl Iterator<Item = &'a File>,
+        client: Option<Client>,
     ) -> Result<(), Vec<(Error, File)>> {
         let client = client.unwrap_or_else(Client::new);
-        let mut downloads: Vec<_> = self
-            .index
-            .files
-            .iter()
-            .filter(|file| {
-                descriptor_types
-                    .iter()
-                    .any(|ttype| file.type_matches(ttype))
-                    && file.overlap(&time_range)
-            })
+        let mut downloads: Vec<_> = files
             .map(|file| FileDownloader::new(file, self))
             // insert dummy error to make the type match
             .map(|dl| (Error::Collector(ErrorKind::HashMissmatch), dl))
             .collect();
 
-        for _ in 0..3 {
+        for _ in 0..self.retry_max_attempts {
             downloads = stream::iter(downloads.into_iter().map(|download| {
                 download
                     .1
                     .download(client.clone(), self.index_url.is_some())
             }))
-            .buffer_unordered(num_cpus::get())
+            .buffer_unordered(self.download_concurrency)
             .filter_map(|res| async { res.err() })
             .collect()
             .await;
         }
+        if let Some(download_state) = &self.download_state {
+            // failing to save only means files will be hashed again
+            if let Err(_e) = download_state.save().await {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %_e, "failed to save download state");
+            }
+        }
         if downloads.is_empty() {
             Ok(())
         } else {
@@ -125,42 +379,262 @@ impl CollecTor {
         ttype: Type,
         time_range: R,
     ) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + '_ {
-        stream::iter(
+        stream::iter(skip_overlapping(
             self.index
                 .files
                 .iter()
                 .filter(move |file| file.type_matches(&ttype) && file.overlap(&time_range))
-                .scan(BoundedSet::empty(), |ranges, file| {
-                    // assumption: archives don't overlap, and appear first (which is true
-                    // because archive/ < recent/
-                    if file.is_archive() || ranges.clone().disjoint(file.time_range()) {
-                        // could be cleaner if BoundedSet impl Default or union took &self/&mut self
-                        *ranges =
-                            std::mem::replace(ranges, BoundedSet::empty()).union(file.time_range());
-                        Some(Some(file))
-                    } else {
-                        Some(None)
-                    }
-                })
-                .flatten(),
-        )
-        .flat_map(|file| {
+                .map(move |file| (file, self)),
+        ))
+        .flat_map(|(file, collector)| {
+            collector
+                .file_to_descriptor_stream(file)
+                .map_err(|e| (file.clone(), e))
+        })
+    }
+
+    /// Get the first descriptor [`CollecTor::stream_descriptors`] would return, if any.
+    pub async fn first_descriptor<R: 'static + RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: Type,
+        time_range: R,
+    ) -> Result<Option<Descriptor>, Error> {
+        Box::pin(self.stream_descriptors(ttype, time_range))
+            .next()
+            .await
+            .transpose()
+            .map_err(|(_, e)| e)
+    }
+
+    /// Stream descriptors of type `ttype` published during `time_range`, from archives only.
+    ///
+    /// Unlike [`CollecTor::stream_descriptors`], overlapping files aren't skipped.
+    pub fn stream_archive_only<R: 'static + RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: Type,
+        time_range: R,
+    ) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + '_ {
+        self.stream_files_where(ttype, time_range, File::is_archive)
+    }
+
+    /// Stream descriptors of type `ttype` published during `time_range`, from recent files
+    /// only, excluding archives.
+    ///
+    /// Unlike [`CollecTor::stream_descriptors`], overlapping files aren't skipped.
+    pub fn stream_recent_only<R: 'static + RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: Type,
+        time_range: R,
+    ) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + '_ {
+        self.stream_files_where(ttype, time_range, |file| !file.is_archive())
+    }
+
+    fn stream_files_where<R: 'static + RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: Type,
+        time_range: R,
+        predicate: fn(&File) -> bool,
+    ) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + '_ {
+        stream::iter(self.index.files.iter().filter(move |file| {
+            file.type_matches(&ttype) && file.overlap(&time_range) && predicate(file)
+        }))
+        .flat_map(move |file| {
             self.file_to_descriptor_stream(file)
                 .map_err(|e| (file.clone(), e))
         })
     }
 
+    /// Like [`CollecTor::stream_descriptors`], but skip descriptors with a fingerprint and
+    /// publication time already seen, as the same descriptor can appear in multiple files.
+    ///
+    /// At most `max_tracked` descriptors are remembered, older ones being forgotten first.
+    /// Descriptors without fingerprint or publication time are never skipped.
+    pub fn stream_descriptors_dedup<R: 'static + RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: Type,
+        time_range: R,
+        max_tracked: usize,
+    ) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + '_ {
+        let mut seen = HashSet::new();
+        let mut order = VecDeque::new();
+        self.stream_descriptors(ttype, time_range)
+            .filter(move |desc| {
+                let identity = desc
+                    .as_ref()
+                    .ok()
+                    .and_then(|desc| desc.identity())
+                    .map(|(fingerprint, time)| (fingerprint.to_owned(), time));
+                let keep = match identity {
+                    Some(identity) if seen.contains(&identity) => false,
+                    Some(identity) => {
+                        if max_tracked > 0 {
+                            if order.len() == max_tracked {
+                                if let Some(oldest) = order.pop_front() {
+                                    seen.remove(&oldest);
+                                }
+                            }
+                            seen.insert(identity.clone());
+                            order.push_back(identity);
+                        }
+                        true
+                    }
+                    None => true,
+                };
+                futures::future::ready(keep)
+            })
+    }
+
     pub fn file_to_descriptor_stream<'a>(
         &'a self,
         file: &'a File,
     ) -> impl Stream<Item = Result<Descriptor, Error>> + 'a {
-        FileReader::read_file(self.file_path(file))
-            .and_then(|s| futures::future::ready(Descriptor::decode(&s)))
+        FileReader::read_file(self.file_path(file)).and_then(move |s| {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(file = %file.path, len = s.len(), "decoding descriptor");
+            let desc = Descriptor::decode(&s);
+            #[cfg(feature = "tracing")]
+            if let Ok(desc) = &desc {
+                tracing::debug!(file = %file.path, descriptor = %desc, "decoded descriptor");
+            }
+            futures::future::ready(desc)
+        })
+    }
+
+    /// Read the content of `file` as stored locally, without decompressing nor parsing it.
+    pub async fn read_file_bytes(&self, file: &File) -> Result<Bytes, Error> {
+        let path = self.file_path(file);
+        fs::read(&path)
+            .await
+            .map(Bytes::from)
+            .map_err(|e| Error::from(e).in_file(path, None))
+    }
+
+    /// Like [`CollecTor::file_to_descriptor_stream`], but only return descriptors of type `T`,
+    /// silently skipping the others.
+    pub fn file_to_descriptor_stream_typed<'a, T: TryFrom<Descriptor> + 'a>(
+        &'a self,
+        file: &'a File,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a {
+        self.file_to_descriptor_stream(file).filter_map(|desc| {
+            futures::future::ready(match desc {
+                Ok(desc) => T::try_from(desc).ok().map(Ok),
+                Err(e) => Some(Err(e)),
+            })
+        })
+    }
+
+    /// Count, for each type in the index, how many files containing it are available locally.
+    ///
+    /// This counts files, not descriptors, and doesn't verify the hash of local files, only
+    /// their size. No network access is made.
+    pub fn descriptor_count_estimate_by_type(&self) -> HashMap<Type, usize> {
+        let mut counts = HashMap::new();
+        for file in &self.index.files {
+            let cached = self.is_cached(file);
+            for vt in &file.types {
+                *counts.entry(vt.ttype.clone()).or_insert(0) += cached as usize;
+            }
+        }
+        counts
+    }
+
+    /// Get, for each type in the index, the range of dates covered by files available locally,
+    /// or None if no such file is available. No network access is made.
+    pub fn descriptor_coverage(&self) -> HashMap<Type, Option<RangeInclusive<DateTime<Utc>>>> {
+        let mut coverage: HashMap<Type, Option<RangeInclusive<DateTime<Utc>>>> = HashMap::new();
+        for file in &self.index.files {
+            let cached = self.is_cached(file);
+            for vt in &file.types {
+                let range = coverage.entry(vt.ttype.clone()).or_insert(None);
+                if !cached {
+                    continue;
+                }
+                *range = Some(match range.take() {
+                    Some(range) => {
+                        (*range.start()).min(file.first_published)
+                            ..=(*range.end()).max(file.last_published)
+                    }
+                    None => file.time_range(),
+                });
+            }
+        }
+        coverage
+    }
+
+    fn is_cached(&self, file: &File) -> bool {
+        std::fs::metadata(self.file_path(file))
+            .map(|meta| meta.is_file() && meta.len() == file.size)
+            .unwrap_or(false)
+    }
+
+    fn index_file_path(&self) -> PathBuf {
+        self.index_path
+            .clone()
+            .unwrap_or_else(|| self.base_path.join("index.json"))
     }
 
     fn file_path(&self, file: &File) -> PathBuf {
-        self.base_path.join(&file.path)
+        let base_path = file
+            .types
+            .iter()
+            .find_map(|vt| self.type_paths.get(&vt.ttype))
+            .unwrap_or(&self.base_path);
+        base_path.join(&file.path)
+    }
+}
+
+/// Keep only files which don't overlap with files seen before them. Archives are always kept,
+/// and are visited before any other file, then files are visited in chronological order.
+fn skip_overlapping<'a, T>(
+    files: impl Iterator<Item = (&'a File, T)>,
+) -> impl Iterator<Item = (&'a File, T)> {
+    let mut files: Vec<_> = files.collect();
+    // a recent file can start before the archive covering it, so chronological order alone
+    // doesn't put archives first
+    files.sort_by(|(a, _), (b, _)| (!a.is_archive(), *a).cmp(&(!b.is_archive(), *b)));
+    files
+        .into_iter()
+        .scan(BoundedSet::empty(), |ranges, (file, item)| {
+            // assumption: archives don't overlap
+            if file.is_archive() || ranges.clone().disjoint(file.time_range()) {
+                // could be cleaner if BoundedSet impl Default or union took &self/&mut self
+                *ranges = std::mem::replace(ranges, BoundedSet::empty()).union(file.time_range());
+                Some(Some((file, item)))
+            } else {
+                Some(None)
+            }
+        })
+        .flatten()
+}
+
+/// Stream descriptors of type `ttype` from the union of the indexes of `instances`, such as a
+/// primary instance and its mirrors. Files are identified by their hash, and each is read from
+/// the first instance which has it locally, or the first one listing it if none do. Overlapping
+/// files are skipped like in [`CollecTor::stream_descriptors`].
+pub fn stream_from_any<'a, R: RangeBounds<DateTime<Utc>>>(
+    instances: &'a [CollecTor],
+    ttype: Type,
+    time_range: R,
+) -> impl Stream<Item = Result<Descriptor, (File, Error)>> + 'a {
+    let mut files: HashMap<[u8; 32], (&File, &CollecTor)> = HashMap::new();
+    for instance in instances {
+        for file in instance.index.files_in_range(&ttype, &time_range) {
+            files
+                .entry(file.sha256)
+                .and_modify(|(known, known_instance)| {
+                    if !known_instance.is_cached(known) && instance.is_cached(file) {
+                        *known = file;
+                        *known_instance = instance;
+                    }
+                })
+                .or_insert((file, instance));
+        }
     }
+    stream::iter(skip_overlapping(files.into_values())).flat_map(|(file, collector)| {
+        collector
+            .file_to_descriptor_stream(file)
+            .map_err(|e| (file.clone(), e))
+    })
 }
 
 struct FileDownloader<'a> {
@@ -174,7 +648,7 @@ impl<'a> FileDownloader<'a> {
     }
 
     fn data_path(&self) -> PathBuf {
-        self.collector.base_path.join(&self.file.path)
+        self.collector.file_path(self.file)
     }
 
     fn url(&self) -> String {
@@ -191,33 +665,40 @@ impl<'a> FileDownloader<'a> {
             .map_err(|e| (dbg!(e), self))
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip(self, client),
+            fields(
+                path = %self.file.path,
+                expected_size = self.file.size,
+                cache_hit = tracing::field::Empty,
+            )
+        )
+    )]
     async fn download_inner(&self, client: Client, download: bool) -> Result<(), Error> {
         let data_path = self.data_path();
-        if let Ok(mut file) = fs::File::open(&data_path).await {
-            let sha256 = self.file.sha256;
-            let hash_ok = tokio::spawn(async move {
-                let mut buf = vec![0; 256 * 1024];
-                let mut hasher = Sha256::new();
-
-                loop {
-                    let Ok(len) = file.read(&mut buf).await else {
-                        return false;
-                    };
-                    if len == 0 {
-                        break;
-                    }
-                    hasher.update(&buf[..len]);
-                }
-
-                let res = hasher.finalize();
-                res.as_slice() == sha256
-            })
-            .await
-            .unwrap_or(false);
-            if hash_ok {
+        let download_state = self.collector.download_state.as_deref();
+        if let Some(download_state) = download_state {
+            if download_state
+                .is_verified(&self.file.path, &data_path, &self.file.sha256)
+                .await
+            {
+                #[cfg(feature = "tracing")]
+                tracing::Span::current().record("cache_hit", true);
                 return Ok(());
             }
         }
+        let hash_ok = hash_matches(&data_path, &self.file.sha256).await;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("cache_hit", hash_ok);
+        if hash_ok {
+            if let Some(download_state) = download_state {
+                download_state.record(&self.file.path, &self.file.sha256);
+            }
+            return Ok(());
+        }
         if !download {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -226,35 +707,633 @@ impl<'a> FileDownloader<'a> {
             .into());
         }
 
-        let resp = client.get(&self.url()).send().await?;
-        if resp.status() != StatusCode::OK {
-            return Err(ErrorKind::HttpError(resp.status().as_u16()).into());
+        fetch_file(
+            &client,
+            &self.url(),
+            &data_path,
+            &self.file.sha256,
+            Some(self.file.size),
+        )
+        .await?;
+        if let Some(download_state) = download_state {
+            download_state.record(&self.file.path, &self.file.sha256);
         }
+        Ok(())
+    }
+}
 
-        if resp
-            .content_length()
-            .map(|len| len != self.file.size)
-            .unwrap_or(false)
-        {
-            // if len is wrong, hash will be too, don't bother receiving the whole file
-            return Err(ErrorKind::HashMissmatch.into());
-        }
+/// Download the file at `url` to `dest`, and verify its hash. Nothing is downloaded if `dest`
+/// already has the expected hash.
+///
+/// This doesn't require a [`CollecTor`], nor loading the index.
+pub async fn download_file(
+    url: &str,
+    dest: &Path,
+    expected_sha256: &[u8; 32],
+    client: Option<Client>,
+) -> Result<(), Error> {
+    if hash_matches(dest, expected_sha256).await {
+        return Ok(());
+    }
+    let client = client.unwrap_or_default();
+    fetch_file(&client, url, dest, expected_sha256, None).await
+}
+
+/// Decompress an xz-compressed buffer.
+async fn decompress_xz(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    XzDecoder::new(data).read_to_end(&mut decompressed).await?;
+    Ok(decompressed)
+}
 
-        fs::create_dir_all(data_path.parent().expect("there is always a parent")).await?;
-        let mut file = fs::File::create(&data_path).await?;
+/// Whether the file at `path` exists and has the given sha256.
+async fn hash_matches(path: &Path, sha256: &[u8; 32]) -> bool {
+    let Ok(mut file) = fs::File::open(path).await else {
+        return false;
+    };
+    let sha256 = *sha256;
+    tokio::spawn(async move {
+        let mut buf = vec![0; 256 * 1024];
         let mut hasher = Sha256::new();
-        let mut stream = resp.bytes_stream();
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            hasher.update(&chunk);
-            file.write_all(&chunk).await?;
+
+        loop {
+            let Ok(len) = file.read(&mut buf).await else {
+                return false;
+            };
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
         }
-        file.flush().await?;
+
         let res = hasher.finalize();
-        if res.as_slice() != self.file.sha256 {
-            return Err(ErrorKind::HashMissmatch.into());
+        res.as_slice() == sha256
+    })
+    .await
+    .unwrap_or(false)
+}
+
+async fn fetch_file(
+    client: &Client,
+    url: &str,
+    dest: &Path,
+    sha256: &[u8; 32],
+    expected_size: Option<u64>,
+) -> Result<(), Error> {
+    let resp = client.get(url).send().await?;
+    if resp.status() != StatusCode::OK {
+        return Err(ErrorKind::HttpError(resp.status().as_u16()).into());
+    }
+
+    if resp
+        .content_length()
+        .zip(expected_size)
+        .map(|(len, size)| len != size)
+        .unwrap_or(false)
+    {
+        // if len is wrong, hash will be too, don't bother receiving the whole file
+        return Err(ErrorKind::HashMissmatch.into());
+    }
+
+    let body = resp
+        .bytes_stream()
+        .map_err(std::io::Error::other)
+        .into_async_read()
+        .compat();
+    atomic_write(dest, body, Some(sha256)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_INDEX: &str = r#"{
+  "index_created": "2022-07-18 00:00",
+  "path": "https://collector.torproject.org",
+  "directories": [{
+    "path": "recent",
+    "directories": [{
+      "path": "bridge-descriptors",
+      "directories": [{
+        "path": "extra-infos",
+        "files": [{
+          "path": "2022-07-17-18-07-19-extra-infos",
+          "size": 2430,
+          "last_modified": "2022-07-17 18:30",
+          "types": ["bridge-extra-info 1.3"],
+          "first_published": "2022-07-17 18:07",
+          "last_published": "2022-07-17 18:07",
+          "sha256": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        }]
+      }]
+    }]
+  }]
+}"#;
+
+    /// Create an offline CollecTor from a builder, with a small index.
+    async fn offline_collector(builder: CollecTorBuilder, base_path: &Path) -> CollecTor {
+        fs::create_dir_all(base_path).await.unwrap();
+        fs::write(base_path.join("index.json"), TEST_INDEX)
+            .await
+            .unwrap();
+        builder.index_url(None).build().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_index_from_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+
+        let index = Index::from_slice(TEST_INDEX.as_bytes()).unwrap();
+        assert_eq!(&index, collector.index());
+        assert_eq!(index, TEST_INDEX.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_decompress_index() {
+        use async_compression::tokio::bufread::XzEncoder;
+
+        let mut compressed = Vec::new();
+        XzEncoder::new(TEST_INDEX.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        let json = decompress_xz(&compressed).await.unwrap();
+        assert_eq!(json, TEST_INDEX.as_bytes());
+        assert!(decompress_xz(TEST_INDEX.as_bytes()).await.is_err());
+
+        let builder =
+            CollecTor::builder("unused").index_url_compressed("https://example.com/index");
+        assert!(builder.index_compressed);
+        assert!(!builder.index_url(None).index_compressed);
+    }
+
+    #[tokio::test]
+    async fn test_offline_without_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("recent/bridge-descriptors/extra-infos/2022-07-17-18-07-19-extra-infos");
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::copy("tests/bridge_extra_info_test", &path)
+            .await
+            .unwrap();
+
+        let collector = CollecTor::new_with_url(dir.path(), None).await.unwrap();
+        assert_eq!(collector.index().files.len(), 1);
+        let descriptors: Vec<_> = collector
+            .stream_descriptors(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert_eq!(descriptors.len(), 1);
+        assert!(descriptors[0].is_ok());
+
+        let index_path = dir.path().join("elsewhere.json");
+        assert!(CollecTor::builder(dir.path())
+            .index_url(None)
+            .index_path(&index_path)
+            .build()
+            .await
+            .is_err());
+        fs::write(&index_path, TEST_INDEX).await.unwrap();
+        let collector = CollecTor::builder(dir.path())
+            .index_url(None)
+            .index_path(&index_path)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(collector.index(), &TEST_INDEX.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_offline_without_index_type_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let type_path = dir.path().join("extra-infos-data");
+        let path =
+            type_path.join("recent/bridge-descriptors/extra-infos/2022-07-17-18-07-19-extra-infos");
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::copy("tests/bridge_extra_info_test", &path)
+            .await
+            .unwrap();
+
+        let collector = CollecTor::builder(dir.path())
+            .index_url(None)
+            .type_path(Type::BridgeExtraInfo, &type_path)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(collector.index().files.len(), 1);
+        let file = collector.index().files.iter().next().unwrap();
+        assert_eq!(collector.file_path(file), path);
+        let descriptors: Vec<_> = collector
+            .stream_descriptors(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert_eq!(descriptors.len(), 1);
+        assert!(descriptors[0].is_ok());
+
+        // the index built is kept for next time
+        let saved = Index::from_file(dir.path().join("index.json"))
+            .await
+            .unwrap();
+        assert_eq!(saved.files.len(), 1);
+        let saved_file = saved.files.iter().next().unwrap();
+        assert_eq!(saved_file.path, file.path);
+        assert_eq!(saved_file.sha256, file.sha256);
+    }
+
+    #[tokio::test]
+    async fn test_stream_archive_or_recent_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+        let file = collector.index().files.iter().next().unwrap();
+        let path = collector.file_path(file);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::copy("tests/bridge_extra_info_test", path)
+            .await
+            .unwrap();
+
+        let recent: Vec<_> = collector
+            .stream_recent_only(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert_eq!(recent.len(), 1);
+        assert!(recent[0].is_ok());
+        let archive: Vec<_> = collector
+            .stream_archive_only(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert!(archive.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("collector.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "base_path = {:?}\noffline = true\nretry_max_attempts = 5\n\n[type_paths]\nbridge-extra-info = {:?}\n",
+                dir.path(),
+                dir.path().join("extra-info"),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let config = CollecTorConfig::from_file(&config_path).unwrap();
+        assert!(config.offline);
+        assert_eq!(config.download_concurrency, None);
+        assert_eq!(config.retry_max_attempts, Some(5));
+
+        let collector = offline_collector(CollecTorBuilder::from_config(config), dir.path()).await;
+        assert_eq!(collector.index_url, None);
+        assert_eq!(collector.retry_max_attempts, 5);
+        assert_eq!(collector.download_concurrency, num_cpus::get());
+        assert_eq!(
+            collector.type_paths.get(&Type::BridgeExtraInfo),
+            Some(&dir.path().join("extra-info"))
+        );
+
+        fs::write(&config_path, "base_path = \"/tmp\"\nunknown = 1\n")
+            .await
+            .unwrap();
+        assert!(CollecTorConfig::from_file(&config_path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_new_since() {
+        use chrono::TimeZone;
+
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+
+        // the only file was modified before, so it isn't even checked
+        let after = Utc.ymd(2022, 7, 18).and_hms(0, 0, 0);
+        assert!(collector
+            .download_new_since(&[Type::BridgeExtraInfo], after, None)
+            .await
+            .is_ok());
+
+        // the file is missing and can't be downloaded while offline
+        let before = Utc.ymd(2022, 7, 17).and_hms(0, 0, 0);
+        let errors = collector
+            .download_new_since(&[Type::BridgeExtraInfo], before, None)
+            .await
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(collector
+            .download_new_since(&[Type::BridgeServerDescriptor], before, None)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_type_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let extra_info_dir = dir.path().join("extra-info");
+        let collector = offline_collector(
+            CollecTor::builder(dir.path()).type_path(Type::BridgeExtraInfo, &extra_info_dir),
+            dir.path(),
+        )
+        .await;
+
+        let file = collector.index().files.iter().next().unwrap();
+        assert_eq!(
+            collector.file_path(file),
+            extra_info_dir
+                .join("recent/bridge-descriptors/extra-infos/2022-07-17-18-07-19-extra-infos")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+
+        assert_eq!(
+            collector.descriptor_count_estimate_by_type(),
+            HashMap::from([(Type::BridgeExtraInfo, 0)])
+        );
+        assert_eq!(
+            collector.descriptor_coverage(),
+            HashMap::from([(Type::BridgeExtraInfo, None)])
+        );
+
+        let file = collector.index().files.iter().next().unwrap();
+        let path = collector.file_path(file);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::write(&path, vec![0; file.size as usize]).await.unwrap();
+
+        assert_eq!(
+            collector.descriptor_count_estimate_by_type(),
+            HashMap::from([(Type::BridgeExtraInfo, 1)])
+        );
+        assert_eq!(
+            collector.descriptor_coverage(),
+            HashMap::from([(Type::BridgeExtraInfo, Some(file.time_range()))])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_descriptors_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        // the same descriptor, in two files the index claims don't overlap
+        let index = TEST_INDEX.replace(
+            "}]\n      }]",
+            r#"}, {
+          "path": "2022-07-18-18-07-19-extra-infos",
+          "size": 2430,
+          "last_modified": "2022-07-18 18:30",
+          "types": ["bridge-extra-info 1.3"],
+          "first_published": "2022-07-18 18:07",
+          "last_published": "2022-07-18 18:07",
+          "sha256": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        }]
+      }]"#,
+        );
+        fs::write(dir.path().join("index.json"), index)
+            .await
+            .unwrap();
+        let collector = CollecTor::builder(dir.path())
+            .index_url(None)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(collector.index().files.len(), 2);
+        let descriptor = fs::read("tests/bridge_extra_info_test").await.unwrap();
+        for file in &collector.index().files {
+            let path = collector.file_path(file);
+            fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+            fs::write(path, &descriptor).await.unwrap();
         }
 
-        Ok(())
+        let all: Vec<_> = collector
+            .stream_descriptors(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert_eq!(all.len(), 2);
+        let dedup: Vec<_> = collector
+            .stream_descriptors_dedup(Type::BridgeExtraInfo, .., 16)
+            .collect()
+            .await;
+        assert_eq!(dedup.len(), 1);
+        assert!(dedup[0].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stream_from_any() {
+        let primary_dir = tempfile::tempdir().unwrap();
+        let mirror_dir = tempfile::tempdir().unwrap();
+        let primary =
+            offline_collector(CollecTor::builder(primary_dir.path()), primary_dir.path()).await;
+        let mirror =
+            offline_collector(CollecTor::builder(mirror_dir.path()), mirror_dir.path()).await;
+
+        // only the mirror has the file locally
+        let file = mirror.index().files.iter().next().unwrap();
+        let path = mirror.file_path(file);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::copy("tests/bridge_extra_info_test", path)
+            .await
+            .unwrap();
+
+        let instances = [primary, mirror];
+        let from_primary: Vec<_> = instances[0]
+            .stream_descriptors(Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert!(from_primary[0].is_err());
+
+        let from_any: Vec<_> = stream_from_any(&instances, Type::BridgeExtraInfo, ..)
+            .collect()
+            .await;
+        assert_eq!(from_any.len(), 1);
+        assert!(from_any[0].is_ok());
+    }
+
+    #[test]
+    fn test_skip_overlapping() {
+        use chrono::TimeZone;
+
+        let file = |path: &str, first: DateTime<Utc>, last: DateTime<Utc>| File {
+            path: path.to_owned(),
+            size: 0,
+            last_modified: last,
+            types: Vec::new(),
+            first_published: first,
+            last_published: last,
+            sha256: [0; 32],
+        };
+        let files = [
+            // starts before the archive, but is covered by it
+            file(
+                "recent/bridge-descriptors/extra-infos/2022-07-01-00-00-00-extra-infos",
+                Utc.ymd(2022, 6, 30).and_hms(23, 0, 0),
+                Utc.ymd(2022, 7, 1).and_hms(1, 0, 0),
+            ),
+            file(
+                "archive/bridge-descriptors/extra-infos/bridge-extra-infos-2022-07.tar.xz",
+                Utc.ymd(2022, 7, 1).and_hms(0, 30, 0),
+                Utc.ymd(2022, 7, 31).and_hms(23, 0, 0),
+            ),
+            file(
+                "recent/bridge-descriptors/extra-infos/2022-08-01-00-00-00-extra-infos",
+                Utc.ymd(2022, 8, 1).and_hms(0, 0, 0),
+                Utc.ymd(2022, 8, 1).and_hms(1, 0, 0),
+            ),
+        ];
+        assert!(files[0] < files[1]);
+
+        let kept: Vec<_> = skip_overlapping(files.iter().map(|file| (file, ())))
+            .map(|(file, _)| file.path.as_str())
+            .collect();
+        assert_eq!(kept, [files[1].path.as_str(), files[2].path.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn test_first_descriptor() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+
+        assert!(collector
+            .first_descriptor(Type::BridgeServerDescriptor, ..)
+            .await
+            .unwrap()
+            .is_none());
+        // the file isn't available locally
+        assert!(collector
+            .first_descriptor(Type::BridgeExtraInfo, ..)
+            .await
+            .is_err());
+
+        let file = collector.index().files.iter().next().unwrap();
+        let path = collector.file_path(file);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::copy("tests/bridge_extra_info_test", path)
+            .await
+            .unwrap();
+        let desc = collector
+            .first_descriptor(Type::BridgeExtraInfo, ..)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(desc.as_bridge_extra_info().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+        let file = collector.index().files.iter().next().unwrap();
+
+        let err = collector.read_file_bytes(file).await.unwrap_err();
+        assert!(err.to_string().contains(&file.path));
+
+        let path = collector.file_path(file);
+        fs::create_dir_all(path.parent().unwrap()).await.unwrap();
+        fs::write(path, b"raw content").await.unwrap();
+        assert_eq!(
+            collector.read_file_bytes(file).await.unwrap(),
+            &b"raw content"[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_file_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("file");
+        fs::write(&dest, b"content").await.unwrap();
+        let sha256: [u8; 32] = Sha256::digest(b"content").into();
+
+        // file is already there, the url is never queried
+        download_file("http://127.0.0.1:1/file", &dest, &sha256, None)
+            .await
+            .unwrap();
+        assert!(
+            download_file("http://127.0.0.1:1/file", &dest, &[0; 32], None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clone() {
+        let dir = tempfile::tempdir().unwrap();
+        let collector = offline_collector(CollecTor::builder(dir.path()), dir.path()).await;
+
+        let mut cloned = collector.clone();
+        cloned.base_path = dir.path().join("other");
+
+        assert_eq!(collector.base_path, dir.path());
+        assert_eq!(cloned.index(), collector.index());
+        let file = collector.index().files.iter().next().unwrap();
+        assert_ne!(collector.file_path(file), cloned.file_path(file));
+    }
+
+    /// Parse the most recent month of every supported descriptor type from a real CollecTor
+    /// data directory, set in `COLLECTOR_TEST_CORPUS`, and check almost everything parses.
+    /// Meant to be run manually before releases, with `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_corpus() {
+        use chrono::TimeZone;
+
+        // NetworkStatusMicrodescConsensus3 isn't parsed yet
+        const SUPPORTED_TYPES: [Type; 8] = [
+            Type::BridgeExtraInfo,
+            Type::BridgeNetworkStatus,
+            Type::BridgePoolAssignment,
+            Type::BridgeServerDescriptor,
+            Type::BridgestrapStats,
+            Type::Directory,
+            Type::Microdescriptor,
+            Type::ServerDescriptor,
+        ];
+
+        let Ok(corpus) = std::env::var("COLLECTOR_TEST_CORPUS") else {
+            eprintln!("COLLECTOR_TEST_CORPUS is not set, skipping");
+            return;
+        };
+        let collector = CollecTor::new_with_url(corpus, None).await.unwrap();
+
+        let mut total = 0;
+        let mut errors = 0;
+        for ttype in SUPPORTED_TYPES {
+            let Some(&(year, month)) = collector.index().months_covered(&ttype).last() else {
+                continue;
+            };
+            let start = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+            let end = if month == 12 {
+                Utc.ymd(year + 1, 1, 1)
+            } else {
+                Utc.ymd(year, month + 1, 1)
+            }
+            .and_hms(0, 0, 0);
+
+            let (mut type_total, mut type_errors) = (0, 0);
+            let mut descriptors = Box::pin(collector.stream_descriptors(ttype.clone(), start..end));
+            while let Some(res) = descriptors.next().await {
+                type_total += 1;
+                if let Err((file, e)) = res {
+                    type_errors += 1;
+                    eprintln!("{}: {e}", file.path);
+                }
+            }
+            eprintln!(
+                "{ttype} {year}-{month:02}: {type_errors} errors in {type_total} descriptors"
+            );
+            total += type_total;
+            errors += type_errors;
+        }
+
+        assert!(total > 0, "no descriptor found in corpus");
+        let error_rate = errors as f64 / total as f64;
+        assert!(
+            error_rate <= 0.001,
+            "{errors} errors in {total} descriptors ({:.3}%)",
+            error_rate * 100.0
+        );
     }
 }
@@ -4,38 +4,122 @@ use std::path::Path;
 use std::pin::Pin;
 
 use async_compat::CompatExt;
-use async_compression::tokio::bufread::XzDecoder;
+use async_compression::tokio::bufread::{GzipDecoder, XzDecoder, ZstdDecoder};
 use async_stream::try_stream;
 use async_tar::Archive;
 use futures::io::AsyncReadExt;
 use futures::stream::Stream;
 use tokio::{
     fs,
-    io::{AsyncRead, BufReader},
+    io::{AsyncRead, AsyncReadExt as _, BufReader},
 };
 
+/// How a file is packed, as detected from its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionType {
+    /// Plain text descriptors, possibly multiple separated by `@type` lines
+    Plain,
+    /// Plain text descriptors, compressed
+    Xz,
+    Gz,
+    Zst,
+    Tar,
+    TarXz,
+    TarGz,
+    TarZst,
+}
+
+impl CompressionType {
+    pub fn from_name(name: &str) -> Self {
+        if name.ends_with(".tar") {
+            CompressionType::Tar
+        } else if name.ends_with(".tar.xz") {
+            CompressionType::TarXz
+        } else if name.ends_with(".tar.gz") {
+            CompressionType::TarGz
+        } else if name.ends_with(".tar.zst") {
+            CompressionType::TarZst
+        } else if name.ends_with(".xz") {
+            CompressionType::Xz
+        } else if name.ends_with(".gz") {
+            CompressionType::Gz
+        } else if name.ends_with(".zst") {
+            CompressionType::Zst
+        } else {
+            CompressionType::Plain
+        }
+    }
+
+    /// Whether this is a tar archive, compressed or not.
+    pub fn is_tar(self) -> bool {
+        use CompressionType::*;
+        matches!(self, Tar | TarXz | TarGz | TarZst)
+    }
+}
+
+/// Skip the descriptor at the start of `input`, returning what follows, starting at the next
+/// `@type` line. Returns an empty string if there is no other descriptor.
+pub fn skip_to_next_descriptor(input: &str) -> &str {
+    match input.find("\n@type") {
+        // account for the '\n'
+        Some(idx) => &input[idx + 1..],
+        None => &input[input.len()..],
+    }
+}
+
 pub struct FileReader;
 
 impl FileReader {
     pub fn read_file<P: AsRef<Path>>(path: P) -> impl Stream<Item = Result<String, Error>> {
         try_stream! {
             let path = path.as_ref();
-            let path_string = path.display().to_string();
-            if path_string.ends_with(".tar") || path_string.contains(".tar.") {
-                let reader = BufReader::new(fs::File::open(&path).await?);
-                let reader: Pin<Box<dyn AsyncRead + Send + Sync>> =
-                    if path.extension().map(|ext| ext == "xz").unwrap_or(false) {
-                        Box::pin(XzDecoder::new(reader))
-                    } else {
-                        Box::pin(reader)
-                    };
-                for await entry in Archive::new(reader.compat()).entries()? {
-                    let mut entry = entry?;
+            let file = fs::File::open(&path)
+                .await
+                .map_err(|e| Error::from(e).in_file(path, None))?;
+            for await body in Self::read_from(file, &path.display().to_string()) {
+                yield body?;
+            }
+        }
+    }
+
+    /// Read descriptors from `reader`. `name` is used to detect the format of the content,
+    /// based on its extension, the same way [`FileReader::read_file`] does with a path, and is
+    /// included in errors.
+    pub fn read_from<R: AsyncRead + Send + Sync + Unpin + 'static>(
+        reader: R,
+        name: &str,
+    ) -> impl Stream<Item = Result<String, Error>> {
+        let name = name.to_owned();
+        try_stream! {
+            let compression = CompressionType::from_name(&name);
+            let reader = BufReader::new(reader);
+            let reader: Pin<Box<dyn AsyncRead + Send + Sync>> = match compression {
+                CompressionType::Xz | CompressionType::TarXz => Box::pin(XzDecoder::new(reader)),
+                CompressionType::Gz | CompressionType::TarGz => Box::pin(GzipDecoder::new(reader)),
+                CompressionType::Zst | CompressionType::TarZst => {
+                    Box::pin(ZstdDecoder::new(reader))
+                }
+                CompressionType::Plain | CompressionType::Tar => Box::pin(reader),
+            };
+            if compression.is_tar() {
+                let entries = Archive::new(reader.compat())
+                    .entries()
+                    .map_err(|e| Error::from(e).in_file(&name, None))?;
+                for await entry in entries {
+                    let mut entry = entry.map_err(|e| Error::from(e).in_file(&name, None))?;
                     if !entry.header().entry_type().is_file() {
                         continue;
                     }
+                    let entry_path = entry
+                        .header()
+                        .path()
+                        .ok()
+                        .map(|path| path.to_path_buf().into());
                     let mut body = String::new();
-                    entry.read_to_string(&mut body).await?;
+                    entry
+                        .read_to_string(&mut body)
+                        .await
+                        .map_err(|e| Error::from(e).in_file(&name, entry_path))?;
                     if body.is_empty() {
                         eprintln!("{:?}", entry.header().path().unwrap())
                     }
@@ -43,16 +127,130 @@ impl FileReader {
                     yield body;
                 }
             } else {
-                let body = fs::read_to_string(&path).await?;
+                let mut reader = reader;
+                let mut body = String::new();
+                reader
+                    .read_to_string(&mut body)
+                    .await
+                    .map_err(|e| Error::from(e).in_file(&name, None))?;
+                // each descriptor is yielded on its own, so a malformed one only causes an
+                // error for itself when decoded, not for the rest of the file
                 let mut body = body.as_str();
-                while let Some(idx) = body[..].find("\n@type") {
-                    // account for the '\n'
-                    let idx = idx + 1;
-                    yield body[..idx].to_owned();
-                    body = &body[idx..];
+                loop {
+                    let next = skip_to_next_descriptor(body);
+                    yield body[..body.len() - next.len()].to_owned();
+                    if next.is_empty() {
+                        break;
+                    }
+                    body = next;
                 }
-                yield body.to_owned();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::stream::TryStreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_compression_type() {
+        use CompressionType::*;
+        assert_eq!(CompressionType::from_name("a/b.tar"), Tar);
+        assert_eq!(CompressionType::from_name("a/b.tar.xz"), TarXz);
+        assert_eq!(CompressionType::from_name("a/b.tar.gz"), TarGz);
+        assert_eq!(CompressionType::from_name("a/b.tar.zst"), TarZst);
+        assert_eq!(CompressionType::from_name("a/b.xz"), Xz);
+        assert_eq!(CompressionType::from_name("a/b.gz"), Gz);
+        assert_eq!(CompressionType::from_name("a/b.zst"), Zst);
+        assert!(TarXz.is_tar());
+        assert!(!Xz.is_tar());
+        assert!(!Plain.is_tar());
+        assert_eq!(
+            CompressionType::from_name("recent/2022-07-17-18-07-19-extra-infos"),
+            Plain
+        );
+    }
+
+    #[test]
+    fn test_skip_to_next_descriptor() {
+        let input = "@type a 1.0\nfoo\n@type b 1.0\nbar\n";
+        let next = skip_to_next_descriptor(input);
+        assert_eq!(next, "@type b 1.0\nbar\n");
+        assert_eq!(skip_to_next_descriptor(next), "");
+        assert_eq!(
+            skip_to_next_descriptor("garbage\n@type b 1.0\n"),
+            "@type b 1.0\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_from_memory() {
+        let input: &'static [u8] =
+            b"@type bridge-pool-assignment 1.0\nfoo\n@type bridge-pool-assignment 1.0\nbar\n";
+        let bodies: Vec<String> = FileReader::read_from(input, "in-memory")
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            bodies,
+            [
+                "@type bridge-pool-assignment 1.0\nfoo\n",
+                "@type bridge-pool-assignment 1.0\nbar\n"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_compressed() {
+        use async_compression::tokio::bufread::{GzipEncoder, XzEncoder, ZstdEncoder};
+
+        let input: &'static [u8] =
+            b"@type bridge-pool-assignment 1.0\nfoo\n@type bridge-pool-assignment 1.0\nbar\n";
+        let mut xz = Vec::new();
+        XzEncoder::new(input).read_to_end(&mut xz).await.unwrap();
+        let mut gz = Vec::new();
+        GzipEncoder::new(input).read_to_end(&mut gz).await.unwrap();
+        let mut zst = Vec::new();
+        ZstdEncoder::new(input).read_to_end(&mut zst).await.unwrap();
+
+        for (compressed, name) in [(xz, "a.xz"), (gz, "a.gz"), (zst, "a.zst")] {
+            let bodies: Vec<String> = FileReader::read_from(std::io::Cursor::new(compressed), name)
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(
+                bodies,
+                [
+                    "@type bridge-pool-assignment 1.0\nfoo\n",
+                    "@type bridge-pool-assignment 1.0\nbar\n"
+                ],
+                "{name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_context() {
+        let input: &'static [u8] = b"@type bridge-pool-assignment 1.0\n\xff\n";
+        let err = FileReader::read_from(input, "recent/invalid-utf8")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::InFile { file, entry: None, .. } if file.as_path() == Path::new("recent/invalid-utf8")
+        ));
+        assert!(err
+            .to_string()
+            .starts_with("in recent/invalid-utf8: IO error"));
+
+        let err = FileReader::read_file("tests/does-not-exist")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("in tests/does-not-exist: "));
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Error, ErrorKind};
+
+/// A 160 bit relay or bridge fingerprint.
+///
+/// Parsing accepts both upper and lowercase hexadecimal, and display is always lowercase, so
+/// fingerprints from different sources can be compared and used as map keys without caring
+/// about their original case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint(pub [u8; 20]);
+
+impl Fingerprint {
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = Error;
+
+    fn from_str(fingerprint: &str) -> Result<Self, Error> {
+        if fingerprint.len() != 40 || !fingerprint.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ErrorKind::MalformedDesc(format!(
+                "\"{}\" is not a valid fingerprint",
+                fingerprint
+            ))
+            .into());
+        }
+        let mut bytes = [0; 20];
+        for (byte, hex) in bytes.iter_mut().zip(fingerprint.as_bytes().chunks(2)) {
+            // can't fail: we checked these are ascii hex digits
+            *byte = u8::from_str_radix(std::str::from_utf8(hex).unwrap(), 16).unwrap();
+        }
+        Ok(Fingerprint(bytes))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e: crate::error::Error| Error::custom(format!("invalid value: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_display() {
+        let lower: Fingerprint = "005fd4d7decbb250055b861579e6fdc79ad17bee".parse().unwrap();
+        let upper: Fingerprint = "005FD4D7DECBB250055B861579E6FDC79AD17BEE".parse().unwrap();
+        assert_eq!(lower, upper);
+        assert_eq!(lower.as_bytes()[..3], [0x00, 0x5f, 0xd4]);
+        assert_eq!(
+            upper.to_string(),
+            "005fd4d7decbb250055b861579e6fdc79ad17bee"
+        );
+
+        let map = HashMap::from([(lower, "email")]);
+        assert_eq!(map.get(&upper), Some(&"email"));
+
+        for bad in [
+            "005FD4D7",
+            "005FD4D7DECBB250055B861579E6FDC79AD17BEEFF",
+            "X05FD4D7DECBB250055B861579E6FDC79AD17BEE",
+        ] {
+            assert!(bad.parse::<Fingerprint>().is_err(), "{bad}");
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::{cmp::Ordering, collections::HashMap, fmt, net::SocketAddr, str::FromStr};
 
 use chrono::{DateTime, Utc};
 
 use super::utils::*;
+use super::BridgeServerDescriptor;
 use crate::error::{Error, ErrorKind};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -50,19 +51,169 @@ impl History {
         })
     }
 
+    /// Iterate over samples of `data`, along with the end of the interval each covers.
+    /// `timestamp` is the end of the last interval.
+    pub fn samples(&self) -> impl Iterator<Item = (DateTime<Utc>, u64)> + '_ {
+        let len = self.data.len() as i64;
+        self.data.iter().enumerate().map(move |(i, value)| {
+            let intervals_before_end = (len - 1 - i as i64) * self.duration as i64;
+            (
+                self.timestamp - chrono::Duration::seconds(intervals_before_end),
+                *value,
+            )
+        })
+    }
+
+    /// Average `data` over consecutive, non-overlapping windows of `window_size` samples. The
+    /// last window may be shorter. A `window_size` of 0 is treated as 1.
+    pub fn window_average(&self, window_size: usize) -> Vec<f64> {
+        self.data.chunks(window_size.max(1)).map(mean).collect()
+    }
+
+    /// Average each sample of `data` with its neighbours, in a window of `window_size` samples
+    /// centered on it. Windows are truncated at both ends of `data`. A `window_size` of 0 is
+    /// treated as 1.
+    pub fn rolling_average(&self, window_size: usize) -> Vec<f64> {
+        let window_size = window_size.max(1);
+        let before = (window_size - 1) / 2;
+        let after = window_size - 1 - before;
+        (0..self.data.len())
+            .map(|i| {
+                let start = i.saturating_sub(before);
+                let end = (i + after + 1).min(self.data.len());
+                mean(&self.data[start..end])
+            })
+            .collect()
+    }
+
     fn from_optional_vec(data: Option<&[&str]>) -> Result<Option<History>, Error> {
         data.map(|d| History::from_parsed_vec(d.to_vec()))
             .transpose()
     }
 }
 
+fn mean(values: &[u64]) -> f64 {
+    values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64
+}
+
+impl FromStr for History {
+    type Err = Error;
+
+    /// Parse the values of a history line, such as `2023-01-01 12:00:00 (900 s) 1234,5678`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        History::from_parsed_vec(s.trim_end().split(' ').collect())
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} s) ",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.duration
+        )?;
+        for (i, value) in self.data.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A pluggable transport advertised by a bridge, from a `transport` line.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PluggableTransport {
+    pub name: String,
+    pub address: Option<SocketAddr>,
+    pub params: HashMap<String, String>,
+}
+
+impl PluggableTransport {
+    fn from_values(values: &[&str]) -> Result<PluggableTransport, Error> {
+        let (name, mut rest) = values.split_first().ok_or_else(|| {
+            ErrorKind::MalformedDesc("missing parameters to transport".to_owned())
+        })?;
+
+        let address = match rest.first() {
+            Some(address) if !address.contains('=') => {
+                rest = &rest[1..];
+                Some(address.parse()?)
+            }
+            _ => None,
+        };
+
+        // arguments are usually a single comma separated list, but accept them space separated
+        let params = hashmap_from_kv_vec(
+            rest.iter()
+                .flat_map(|arg| arg.split(','))
+                .filter(|arg| !arg.is_empty())
+                .collect(),
+        )?;
+
+        Ok(PluggableTransport {
+            name: (*name).to_owned(),
+            address,
+            params,
+        })
+    }
+}
+
+/// Padding statistics from a `padding-counts` line. Counts are rounded up to `bin_size`, keys
+/// not present in the line are left to `None`.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct PaddingCounts {
+    pub bin_size: Option<u64>,
+    pub write_drop: Option<u64>,
+    pub write_pad: Option<u64>,
+    pub write_total: Option<u64>,
+    pub read_drop: Option<u64>,
+    pub read_pad: Option<u64>,
+    pub read_total: Option<u64>,
+    pub enabled_read_pad: Option<u64>,
+    pub enabled_read_total: Option<u64>,
+    pub enabled_write_pad: Option<u64>,
+    pub enabled_write_total: Option<u64>,
+    pub max_chanpad_timers: Option<u64>,
+}
+
+impl PaddingCounts {
+    fn parse(values: &[&str]) -> Result<Self, Error> {
+        let mut counts = PaddingCounts::default();
+        for value in values.iter().filter(|value| !value.is_empty()) {
+            let (key, value) = value.split_once('=').ok_or_else(|| {
+                ErrorKind::MalformedDesc("padding-counts values are malformed".to_owned())
+            })?;
+            let field = match key {
+                "bin-size" => &mut counts.bin_size,
+                "write-drop" => &mut counts.write_drop,
+                "write-pad" => &mut counts.write_pad,
+                "write-total" => &mut counts.write_total,
+                "read-drop" => &mut counts.read_drop,
+                "read-pad" => &mut counts.read_pad,
+                "read-total" => &mut counts.read_total,
+                "enabled-read-pad" => &mut counts.enabled_read_pad,
+                "enabled-read-total" => &mut counts.enabled_read_total,
+                "enabled-write-pad" => &mut counts.enabled_write_pad,
+                "enabled-write-total" => &mut counts.enabled_write_total,
+                "max-chanpad-timers" => &mut counts.max_chanpad_timers,
+                _ => continue,
+            };
+            *field = Some(value.parse()?);
+        }
+        Ok(counts)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct BridgeExtraInfo {
     pub timestamp: DateTime<Utc>,
     pub name: String,
     pub fingerprint: String,
     pub master_key: Option<String>,
-    pub transport: Vec<String>,
+    pub transport: Vec<PluggableTransport>,
     pub write_history: Option<History>,
     pub read_history: Option<History>,
     pub write_history_v6: Option<History>,
@@ -77,13 +228,18 @@ pub struct BridgeExtraInfo {
     pub dirreq_v3_resp: Option<HashMap<String, u64>>,
     pub dirreq_v3_direct_dl: Option<HashMap<String, u64>>,
     pub dirreq_v3_tunneled_dl: Option<HashMap<String, u64>>,
+    /// Statistics from a `conn-bi-direct` line: end of the measurement interval and its length
+    /// in seconds, then the number of connections which read and wrote less than 20 KiB
+    /// (below), read at least 10 times more than they wrote (read), wrote at least 10 times
+    /// more than they read (write), and all other connections (both).
+    pub conn_bi_direct: Option<(DateTime<Utc>, u64, u64, u64, u64, u64)>,
     pub hidserv_stats_end: Option<(DateTime<Utc>, u64)>,
     pub hidserv_rend_relayed_cells: Option<(String, HashMap<String, String>)>,
     pub hidserv_dir_onions_seen: Option<(String, HashMap<String, String>)>,
     pub hidserv_v3_stats_end: Option<(DateTime<Utc>, u64)>,
     pub hidserv_rend_v3_relayed_cells: Option<(String, HashMap<String, String>)>,
     pub hidserv_dir_v3_onions_seen: Option<(String, HashMap<String, String>)>,
-    pub padding_counts: Option<(DateTime<Utc>, u64, HashMap<String, u64>)>,
+    pub padding_counts: Option<(DateTime<Utc>, u64, PaddingCounts)>,
     pub bridge_stats_end: Option<(DateTime<Utc>, u64)>,
     pub bridge_ips: Option<HashMap<String, u64>>,
     pub bridge_ip_versions: Option<HashMap<String, u64>>,
@@ -119,18 +275,10 @@ impl BridgeExtraInfo {
                     timestamp: date(&format!("{} {}", day, hour))?.1,
                 },
                 multi("transport") [] => {
-                    // TODO this is less than ideal; multi should support [xx, yy] params
                     transport:
                         rest.iter()
-                            .map(|e| {
-                                Ok(e.values.first()
-                                    .ok_or_else(||
-                                        ErrorKind::MalformedDesc(
-                                            "missing parameters to accept".to_owned()
-                                        ))?
-                                    .to_string())
-                            })
-                           .collect::<Result<Vec<_>, Error>>()?,
+                            .map(|e| PluggableTransport::from_values(&e.values))
+                            .collect::<Result<Vec<_>, Error>>()?,
                 },
                 opt("write-history") [] => {
                     write_history: History::from_optional_vec(rest)?,
@@ -175,6 +323,15 @@ impl BridgeExtraInfo {
                 opt("dirreq-v3-tunneled-dl") [kv] => {
                     dirreq_v3_tunneled_dl: kv.map(|kv| create_kv_u64(kv.split(',').collect())).transpose()?,
                 },
+                opt("conn-bi-direct") [day, hour, duration, _unused, counts] => {
+                    conn_bi_direct:
+                        if let Some((date, duration)) = parse_end(day, hour, duration)? {
+                            let (below, read, write, both) = parse_conn_bi_direct(counts)?;
+                            Some((date, duration, below, read, write, both))
+                        } else {
+                            None
+                        },
+                },
                 opt("hidserv-stats-end") [day, hour, duration] => {
                     hidserv_stats_end: parse_end(day, hour, duration)?,
                 },
@@ -212,7 +369,7 @@ impl BridgeExtraInfo {
                 opt("padding-counts") [day, hour, duration, _unused] => {
                     padding_counts:
                         if let Some((date, duration)) = parse_end(day, hour, duration)? {
-                            Some((date, duration, create_kv_u64(rest.unwrap_or_default().to_vec())?))
+                            Some((date, duration, PaddingCounts::parse(rest.unwrap_or_default())?))
                         } else {
                             None
                         },
@@ -240,6 +397,200 @@ impl BridgeExtraInfo {
         })
     }
 
+    /// Check this is the extra-info document referenced by `descriptor`.
+    ///
+    /// `router_digest` and `router_sha256` are digests of this document, which the server
+    /// descriptor announces in its `extra-info-digest` line. Returns an error if `descriptor`
+    /// doesn't reference any extra-info document.
+    pub fn verify_digest(&self, descriptor: &BridgeServerDescriptor) -> Result<bool, Error> {
+        let expected = descriptor.extra_info.as_ref().ok_or_else(|| {
+            ErrorKind::MalformedDesc("descriptor has no extra-info-digest".to_owned())
+        })?;
+
+        let sha1_ok = expected.sha1.eq_ignore_ascii_case(&self.router_digest);
+        // base64 padding is omitted in descriptors, but don't depend on it
+        let sha256_ok = expected
+            .sha256
+            .as_ref()
+            .map(|sha256| sha256.trim_end_matches('=') == self.router_sha256.trim_end_matches('='))
+            .unwrap_or(true);
+        Ok(sha1_ok && sha256_ok)
+    }
+
+    /// Check that statistics and histories end before this document was published, allowing
+    /// for one hour of clock skew. Returns an error listing every field ending too late.
+    pub fn validate_temporal_consistency(&self) -> Result<(), Error> {
+        let limit = self.timestamp + chrono::Duration::hours(1);
+        let stats_ends = [
+            ("conn-bi-direct", self.conn_bi_direct.map(|(end, ..)| end)),
+            (
+                "bridge-stats-end",
+                self.bridge_stats_end.map(|(end, _)| end),
+            ),
+            (
+                "dirreq-stats-end",
+                self.dirreq_stats_end.map(|(end, _)| end),
+            ),
+            (
+                "hidserv-stats-end",
+                self.hidserv_stats_end.map(|(end, _)| end),
+            ),
+            (
+                "hidserv-v3-stats-end",
+                self.hidserv_v3_stats_end.map(|(end, _)| end),
+            ),
+        ];
+        let history_ends = [
+            ("write-history", &self.write_history),
+            ("read-history", &self.read_history),
+            ("ipv6-write-history", &self.write_history_v6),
+            ("ipv6-read-history", &self.read_history_v6),
+            ("dirreq-write-history", &self.dirreq_write_history),
+            ("dirreq-read-history", &self.dirreq_read_history),
+        ]
+        .map(|(name, history)| (name, history.as_ref().map(|history| history.timestamp)));
+
+        let violations: Vec<_> = stats_ends
+            .into_iter()
+            .chain(history_ends)
+            .filter_map(|(name, end)| Some((name, end?)))
+            .filter(|(_, end)| *end > limit)
+            .map(|(name, end)| format!("{name} ends at {end}"))
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrorKind::MalformedDesc(format!(
+                "published at {}, but {}",
+                self.timestamp,
+                violations.join(", ")
+            ))
+            .into())
+        }
+    }
+
+    /// Sum of cells relayed on rendezvous circuits, from both `hidserv-rend-relayed-cells` and
+    /// `hidserv-rend-v3-relayed-cells`. None if neither line is present.
+    ///
+    /// Values are obfuscated with random noise before being published, and can be negative.
+    pub fn hidserv_total_cells(&self) -> Option<i64> {
+        sum_hidserv_values([
+            &self.hidserv_rend_relayed_cells,
+            &self.hidserv_rend_v3_relayed_cells,
+        ])
+    }
+
+    /// Sum of onion services seen as hidden service directory, from both
+    /// `hidserv-dir-onions-seen` and `hidserv-dir-v3-onions-seen`. None if neither line is
+    /// present.
+    ///
+    /// Values are obfuscated with random noise before being published, and can be negative.
+    pub fn hidserv_onions_seen_total(&self) -> Option<i64> {
+        sum_hidserv_values([
+            &self.hidserv_dir_onions_seen,
+            &self.hidserv_dir_v3_onions_seen,
+        ])
+    }
+
+    /// Iterate over `bridge_ip_transports`, excluding direct connections (`<OR>`).
+    fn transport_users(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.bridge_ip_transports
+            .iter()
+            .flatten()
+            .filter(|(transport, _)| *transport != "<OR>")
+            .map(|(transport, users)| (transport.as_str(), *users))
+    }
+
+    /// Total users connecting through a pluggable transport, excluding direct connections.
+    pub fn total_transport_users(&self) -> u64 {
+        self.transport_users().map(|(_, users)| users).sum()
+    }
+
+    /// Fraction of the users counted by [`BridgeExtraInfo::total_transport_users`] which used
+    /// `transport`. None if there are no such users.
+    pub fn transport_user_fraction(&self, transport: &str) -> Option<f64> {
+        let total = self.total_transport_users();
+        if total == 0 {
+            return None;
+        }
+        let users = self
+            .transport_users()
+            .find(|(name, _)| *name == transport)
+            .map(|(_, users)| users)
+            .unwrap_or(0);
+        Some(users as f64 / total as f64)
+    }
+
+    /// Pluggable transport with the most users, ties broken by name.
+    pub fn dominant_transport(&self) -> Option<&str> {
+        self.transport_users()
+            .max_by(|(name_a, users_a), (name_b, users_b)| {
+                users_a.cmp(users_b).then(name_b.cmp(name_a))
+            })
+            .map(|(name, _)| name)
+    }
+
+    /// Users connecting over IPv4, from `bridge_ip_versions`, 0 if unknown.
+    pub fn ipv4_users(&self) -> u64 {
+        self.ip_version_users("v4")
+    }
+
+    /// Users connecting over IPv6, from `bridge_ip_versions`, 0 if unknown.
+    pub fn ipv6_users(&self) -> u64 {
+        self.ip_version_users("v6")
+    }
+
+    /// Fraction of users connecting over IPv6 rather than IPv4. None if `bridge_ip_versions`
+    /// is missing or has no user.
+    pub fn ipv6_user_fraction(&self) -> Option<f64> {
+        let (v4, v6) = (self.ipv4_users(), self.ipv6_users());
+        if v4 + v6 == 0 {
+            return None;
+        }
+        Some(v6 as f64 / (v4 + v6) as f64)
+    }
+
+    fn ip_version_users(&self, version: &str) -> u64 {
+        self.bridge_ip_versions
+            .as_ref()
+            .and_then(|versions| versions.get(version))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Total directory requests over all countries, from `dirreq_v3_reqs`.
+    pub fn dirreq_v3_total_requests(&self) -> u64 {
+        self.dirreq_v3_reqs
+            .iter()
+            .flat_map(|reqs| reqs.values())
+            .sum()
+    }
+
+    /// Number of countries in `dirreq_v3_reqs`.
+    pub fn dirreq_v3_country_count(&self) -> usize {
+        self.dirreq_v3_reqs.as_ref().map_or(0, HashMap::len)
+    }
+
+    /// Country with the most directory requests, ties broken by country code.
+    pub fn dirreq_v3_top_country(&self) -> Option<(&str, u64)> {
+        top_entry(&self.dirreq_v3_reqs)
+    }
+
+    /// Total unique IPs of directory requests over all countries, from `dirreq_v3_ips`.
+    pub fn dirreq_v3_total_ips(&self) -> u64 {
+        self.dirreq_v3_ips.iter().flat_map(|ips| ips.values()).sum()
+    }
+
+    /// Number of countries in `dirreq_v3_ips`.
+    pub fn dirreq_v3_ip_country_count(&self) -> usize {
+        self.dirreq_v3_ips.as_ref().map_or(0, HashMap::len)
+    }
+
+    /// Country with the most unique IPs of directory requests, ties broken by country code.
+    pub fn dirreq_v3_top_ip_country(&self) -> Option<(&str, u64)> {
+        top_entry(&self.dirreq_v3_ips)
+    }
+
     pub fn empty(timestamp: DateTime<Utc>) -> Self {
         BridgeExtraInfo {
             timestamp,
@@ -261,6 +612,7 @@ impl BridgeExtraInfo {
             dirreq_v3_resp: None,
             dirreq_v3_direct_dl: None,
             dirreq_v3_tunneled_dl: None,
+            conn_bi_direct: None,
             hidserv_stats_end: None,
             hidserv_rend_relayed_cells: None,
             hidserv_dir_onions_seen: None,
@@ -313,6 +665,57 @@ fn parse_end(
         .transpose()
 }
 
+/// Get the key with the highest value, ties broken by key.
+fn top_entry(map: &Option<HashMap<String, u64>>) -> Option<(&str, u64)> {
+    map.iter()
+        .flatten()
+        .max_by(|(key_a, value_a), (key_b, value_b)| value_a.cmp(value_b).then(key_b.cmp(key_a)))
+        .map(|(key, value)| (key.as_str(), *value))
+}
+
+/// Sum the main value of `hidserv-*` statistics, ignoring missing or non-numeric ones.
+fn sum_hidserv_values<const N: usize>(
+    stats: [&Option<(String, HashMap<String, String>)>; N],
+) -> Option<i64> {
+    stats
+        .into_iter()
+        .filter_map(|stat| stat.as_ref()?.0.parse::<i64>().ok())
+        .reduce(|a, b| a + b)
+}
+
+/// Parse the `BELOW,READ,WRITE,BOTH` counts of a `conn-bi-direct` line.
+fn parse_conn_bi_direct(counts: Option<&str>) -> Result<(u64, u64, u64, u64), Error> {
+    let counts = counts
+        .map(|counts| {
+            counts
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<u64>, _>>()
+        })
+        .transpose()?;
+    match counts.as_deref() {
+        Some(&[below, read, write, both]) => Ok((below, read, write, both)),
+        _ => Err(ErrorKind::MalformedDesc("conn-bi-direct must have 4 counts".to_owned()).into()),
+    }
+}
+
+impl fmt::Display for BridgeExtraInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transports = self
+            .transport
+            .iter()
+            .map(|transport| transport.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let bridge_ips: u64 = self.bridge_ips.iter().flat_map(|ips| ips.values()).sum();
+        write!(
+            f,
+            "BridgeExtraInfo {{ name: {}, fp: {}, published: {}, transports: [{}], bridge_ips: {} }}",
+            self.name, self.fingerprint, self.timestamp, transports, bridge_ips
+        )
+    }
+}
+
 impl Ord for BridgeExtraInfo {
     fn cmp(&self, other: &Self) -> Ordering {
         self.timestamp
@@ -326,3 +729,328 @@ impl PartialOrd for BridgeExtraInfo {
         Some(self.cmp(other))
     }
 }
+
+/// Wrapper around [`BridgeExtraInfo`] ordering by fingerprint first, then by timestamp.
+///
+/// `BridgeExtraInfo` itself is ordered chronologically, which suits streaming. This wrapper
+/// instead groups descriptors of the same bridge together, in chronological order, which
+/// suits per-bridge analysis in a `BTreeSet`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BridgeExtraInfoByRelay(pub BridgeExtraInfo);
+
+impl Ord for BridgeExtraInfoByRelay {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .fingerprint
+            .cmp(&other.0.fingerprint)
+            .then(self.0.timestamp.cmp(&other.0.timestamp))
+    }
+}
+
+impl PartialOrd for BridgeExtraInfoByRelay {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<BridgeExtraInfo> for BridgeExtraInfoByRelay {
+    fn from(extra_info: BridgeExtraInfo) -> Self {
+        BridgeExtraInfoByRelay(extra_info)
+    }
+}
+
+impl std::ops::Deref for BridgeExtraInfoByRelay {
+    type Target = BridgeExtraInfo;
+
+    fn deref(&self) -> &BridgeExtraInfo {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_by_relay_order() {
+        use chrono::TimeZone;
+        use std::collections::BTreeSet;
+
+        let desc = |fingerprint: &str, hour| {
+            let mut desc = BridgeExtraInfo::empty(Utc.ymd(2022, 1, 1).and_hms(hour, 0, 0));
+            desc.fingerprint = fingerprint.to_owned();
+            desc
+        };
+        let descs = [desc("B", 1), desc("A", 2), desc("B", 0), desc("A", 1)];
+
+        let by_relay: Vec<_> = descs
+            .into_iter()
+            .map(BridgeExtraInfoByRelay::from)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|desc| (desc.fingerprint.clone(), desc.timestamp))
+            .collect();
+        let expected: Vec<_> = [desc("A", 1), desc("A", 2), desc("B", 0), desc("B", 1)]
+            .into_iter()
+            .map(|desc| (desc.fingerprint, desc.timestamp))
+            .collect();
+        assert_eq!(by_relay, expected);
+    }
+
+    #[test]
+    fn test_parse_transport() {
+        let transport = PluggableTransport::from_values(&["obfs4"]).unwrap();
+        assert_eq!(transport.name, "obfs4");
+        assert_eq!(transport.address, None);
+        assert!(transport.params.is_empty());
+
+        let transport =
+            PluggableTransport::from_values(&["obfs4", "0.0.0.0:1234", "cert=abc,iat-mode=0"])
+                .unwrap();
+        assert_eq!(transport.name, "obfs4");
+        assert_eq!(transport.address, Some("0.0.0.0:1234".parse().unwrap()));
+        assert_eq!(transport.params["cert"], "abc");
+        assert_eq!(transport.params["iat-mode"], "0");
+    }
+
+    #[test]
+    fn test_display() {
+        use chrono::TimeZone;
+
+        let mut desc = BridgeExtraInfo::empty(Utc.ymd(2022, 7, 17).and_hms(18, 7, 19));
+        desc.name = "Sr2Sponsor96Br259".to_owned();
+        desc.fingerprint = "C5E0691ED1AA350DA1DA23D82B0A86FF75AD6E16".to_owned();
+        desc.transport = vec![
+            PluggableTransport::from_values(&["obfs4"]).unwrap(),
+            PluggableTransport::from_values(&["meek"]).unwrap(),
+        ];
+        desc.bridge_ips = Some(HashMap::from([("ru".to_owned(), 64), ("cn".to_owned(), 8)]));
+        assert_eq!(
+            desc.to_string(),
+            "BridgeExtraInfo { name: Sr2Sponsor96Br259, fp: C5E0691ED1AA350DA1DA23D82B0A86FF75AD6E16, \
+             published: 2022-07-17 18:07:19 UTC, transports: [obfs4, meek], bridge_ips: 72 }"
+        );
+    }
+
+    #[test]
+    fn test_transport_users() {
+        let mut desc = BridgeExtraInfo::empty(Utc::now());
+        assert_eq!(desc.total_transport_users(), 0);
+        assert_eq!(desc.transport_user_fraction("obfs4"), None);
+        assert_eq!(desc.dominant_transport(), None);
+
+        desc.bridge_ip_transports = Some(HashMap::from([
+            ("<OR>".to_owned(), 400),
+            ("obfs4".to_owned(), 24),
+            ("snowflake".to_owned(), 8),
+            ("meek".to_owned(), 8),
+        ]));
+        assert_eq!(desc.total_transport_users(), 40);
+        assert_eq!(desc.transport_user_fraction("obfs4"), Some(0.6));
+        assert_eq!(desc.transport_user_fraction("webtunnel"), Some(0.0));
+        assert_eq!(desc.transport_user_fraction("<OR>"), Some(0.0));
+        assert_eq!(desc.dominant_transport(), Some("obfs4"));
+
+        desc.bridge_ip_transports = Some(HashMap::from([
+            ("snowflake".to_owned(), 8),
+            ("meek".to_owned(), 8),
+        ]));
+        assert_eq!(desc.dominant_transport(), Some("meek"));
+    }
+
+    #[test]
+    fn test_ip_versions() {
+        let mut desc = BridgeExtraInfo::empty(Utc::now());
+        assert_eq!(desc.ipv6_user_fraction(), None);
+        assert_eq!(desc.ipv4_users(), 0);
+
+        desc.bridge_ip_versions =
+            Some(HashMap::from([("v4".to_owned(), 80), ("v6".to_owned(), 0)]));
+        assert_eq!(desc.ipv4_users(), 80);
+        assert_eq!(desc.ipv6_users(), 0);
+        assert_eq!(desc.ipv6_user_fraction(), Some(0.0));
+
+        desc.bridge_ip_versions =
+            Some(HashMap::from([("v4".to_owned(), 24), ("v6".to_owned(), 8)]));
+        assert_eq!(desc.ipv6_user_fraction(), Some(0.25));
+
+        desc.bridge_ip_versions = Some(HashMap::from([("v4".to_owned(), 0), ("v6".to_owned(), 0)]));
+        assert_eq!(desc.ipv6_user_fraction(), None);
+    }
+
+    #[test]
+    fn test_dirreq_v3_totals() {
+        let fixture = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let (body, vt) = crate::descriptor::VersionnedType::parse(&fixture).unwrap();
+        let mut desc = BridgeExtraInfo::parse(body, vt.version).unwrap();
+        assert_eq!(desc.dirreq_v3_total_requests(), 64 + 8 * 8);
+        assert_eq!(desc.dirreq_v3_country_count(), 9);
+        assert_eq!(desc.dirreq_v3_top_country(), Some(("ru", 64)));
+        assert_eq!(desc.dirreq_v3_total_ips(), 40 + 8 * 8);
+        assert_eq!(desc.dirreq_v3_ip_country_count(), 9);
+        assert_eq!(desc.dirreq_v3_top_ip_country(), Some(("ru", 40)));
+
+        desc.dirreq_v3_ips = Some(HashMap::from([("us".to_owned(), 8), ("de".to_owned(), 8)]));
+        assert_eq!(desc.dirreq_v3_top_ip_country(), Some(("de", 8)));
+        desc.dirreq_v3_reqs = None;
+        assert_eq!(desc.dirreq_v3_total_requests(), 0);
+        assert_eq!(desc.dirreq_v3_country_count(), 0);
+        assert_eq!(desc.dirreq_v3_top_country(), None);
+    }
+
+    #[test]
+    fn test_temporal_consistency() {
+        use chrono::TimeZone;
+
+        let published = Utc.ymd(2022, 7, 17).and_hms(18, 0, 0);
+        let mut desc = BridgeExtraInfo::empty(published);
+        desc.bridge_stats_end = Some((published - chrono::Duration::hours(2), 86400));
+        desc.write_history = Some(History::empty(published + chrono::Duration::minutes(30)));
+        assert!(desc.validate_temporal_consistency().is_ok());
+
+        desc.dirreq_stats_end = Some((published + chrono::Duration::hours(2), 86400));
+        desc.read_history = Some(History::empty(published + chrono::Duration::days(1)));
+        let err = desc
+            .validate_temporal_consistency()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("dirreq-stats-end"), "{err}");
+        assert!(err.contains("read-history"), "{err}");
+        assert!(!err.contains("write-history"), "{err}");
+
+        let fixture = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let (body, vt) = crate::descriptor::VersionnedType::parse(&fixture).unwrap();
+        let desc = BridgeExtraInfo::parse(body, vt.version).unwrap();
+        assert!(desc.validate_temporal_consistency().is_ok());
+    }
+
+    #[test]
+    fn test_padding_counts() {
+        let counts = PaddingCounts::parse(&[
+            "bin-size=10000",
+            "write-drop=0",
+            "write-pad=40000",
+            "read-total=17680000",
+            "max-chanpad-timers=143",
+            "some-future-key=1",
+        ])
+        .unwrap();
+        assert_eq!(counts.bin_size, Some(10000));
+        assert_eq!(counts.write_drop, Some(0));
+        assert_eq!(counts.write_pad, Some(40000));
+        assert_eq!(counts.read_total, Some(17680000));
+        assert_eq!(counts.max_chanpad_timers, Some(143));
+        assert_eq!(counts.read_pad, None);
+
+        assert!(PaddingCounts::parse(&["bin-size"]).is_err());
+        assert!(PaddingCounts::parse(&["bin-size=many"]).is_err());
+    }
+
+    #[test]
+    fn test_conn_bi_direct() {
+        let fixture = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let (body, vt) = crate::descriptor::VersionnedType::parse(&fixture).unwrap();
+        let desc = BridgeExtraInfo::parse(body, vt.version).unwrap();
+        let (end, duration, below, read, write, both) = desc.conn_bi_direct.unwrap();
+        assert_eq!(end.to_string(), "2022-07-17 14:13:56 UTC");
+        assert_eq!(
+            (duration, below, read, write, both),
+            (86400, 3148, 24, 17, 8)
+        );
+
+        assert_eq!(parse_conn_bi_direct(Some("1,2,3,4")).unwrap(), (1, 2, 3, 4));
+        assert!(parse_conn_bi_direct(Some("1,2,3")).is_err());
+        assert!(parse_conn_bi_direct(Some("1,2,3,x")).is_err());
+        assert!(parse_conn_bi_direct(None).is_err());
+    }
+
+    #[test]
+    fn test_hidserv_totals() {
+        let fixture = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let (body, vt) = crate::descriptor::VersionnedType::parse(&fixture).unwrap();
+        let mut desc = BridgeExtraInfo::parse(body, vt.version).unwrap();
+        assert_eq!(desc.hidserv_total_cells(), Some(10323 + 724));
+        assert_eq!(desc.hidserv_onions_seen_total(), Some(-43 - 20));
+
+        desc.hidserv_rend_relayed_cells = None;
+        assert_eq!(desc.hidserv_total_cells(), Some(724));
+        desc.hidserv_rend_v3_relayed_cells = None;
+        assert_eq!(desc.hidserv_total_cells(), None);
+    }
+
+    #[test]
+    fn test_history_from_str() {
+        let fixture = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let lines: Vec<_> = fixture
+            .lines()
+            .filter_map(|line| line.split_once("-history "))
+            .map(|(_, history)| history)
+            .collect();
+        assert_eq!(lines.len(), 6);
+
+        for line in lines {
+            let history: History = line.parse().unwrap();
+            assert_eq!(history.to_string(), line);
+            assert_eq!(history.duration, 86400);
+            assert_eq!(history.data.len(), 5);
+        }
+
+        let history: History = "2023-01-01 12:00:00 (900 s) 1234,5678,9012"
+            .parse()
+            .unwrap();
+        assert_eq!(history.data, [1234, 5678, 9012]);
+        assert!("2023-01-01 12:00:00 (900 s)".parse::<History>().is_err());
+        assert!("2023-01-01 12:00:00 (900 s) 12,a"
+            .parse::<History>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_history_average() {
+        let mut history = History::empty(Utc::now());
+        assert!(history.window_average(3).is_empty());
+        assert!(history.rolling_average(3).is_empty());
+
+        history.data = vec![1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(history.window_average(3), [2.0, 5.0, 7.0]);
+        assert_eq!(history.window_average(10), [4.0]);
+        assert_eq!(history.window_average(0), history.window_average(1));
+
+        assert_eq!(
+            history.rolling_average(3),
+            [1.5, 2.0, 3.0, 4.0, 5.0, 6.0, 6.5]
+        );
+        assert_eq!(history.rolling_average(20), [4.0; 7]);
+        assert_eq!(
+            history.rolling_average(1),
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]
+        );
+    }
+
+    #[test]
+    fn test_verify_digest() {
+        use crate::descriptor::VersionnedType;
+
+        let raw = std::fs::read_to_string("tests/bridge_extra_info_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let extra_info = BridgeExtraInfo::parse(body, vt.version).unwrap();
+
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let unrelated = BridgeServerDescriptor::parse(body, vt.version).unwrap();
+        assert!(!extra_info.verify_digest(&unrelated).unwrap());
+
+        let body = body.replace(
+            "extra-info-digest C49447D79A3FC483B65943912F5A992EC8ACB39C 8A5vM591ZYqRGOIAEZbp/7PF9KpdHbwpoWcSwioLPfs",
+            "extra-info-digest 252940E90DA8A7441311718EA1ECCC2A29CE0C60 kOVbqwwb+LRRVO0t0jTjTFnleJwvpKRHwdJ5U602d+8",
+        );
+        let mut matching = BridgeServerDescriptor::parse(&body, vt.version).unwrap();
+        assert!(extra_info.verify_digest(&matching).unwrap());
+
+        matching.extra_info.as_mut().unwrap().sha256 = Some(unrelated.router_sha256.unwrap());
+        assert!(!extra_info.verify_digest(&matching).unwrap());
+
+        matching.extra_info = None;
+        assert!(extra_info.verify_digest(&matching).is_err());
+    }
+}
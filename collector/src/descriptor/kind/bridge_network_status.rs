@@ -1,7 +1,7 @@
 use derive_builder;
-use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
-use std::{collections::HashMap, net::Ipv4Addr, vec};
+use std::{net::Ipv4Addr, vec};
 
 use chrono::{DateTime, Utc};
 use derive_builder::Builder;
@@ -9,36 +9,93 @@ use derive_builder::Builder;
 use super::utils::*;
 use crate::error::{Error, ErrorKind};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Header {
+    /// Version from the `network-status-version` line, if the document has one.
+    pub network_status_version: Option<u32>,
     pub published_timestamp: DateTime<Utc>,
-    pub flags: HashMap<String, String>,
+    pub flag_thresholds: FlagThresholds,
+    /// Fingerprint of the bridge authority which generated this status, from the
+    /// `fingerprint` line
     pub fingerprint: String,
+    /// Digests of the bandwidth file used to compute bandwidths, such as `sha256=...`
+    pub bandwidth_file_digest: Option<String>,
 }
 
 impl Header {
+    /// Fingerprint of the bridge authority which generated this status.
+    pub fn authority_fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
     pub fn parse(input: &str) -> Result<Self, Error> {
         use crate::descriptor::nom_combinators::*;
         let mut desc = descriptor_lines(input)?;
         Ok(extract_desc! {
             desc => Header rest {
+                opt("network-status-version") [version] => {
+                    network_status_version: version.map(str::parse).transpose()?,
+                },
                 uniq("published") [day, hour] => {
                     published_timestamp: date(&format!("{} {}", day, hour))?.1,
                 },
                 uniq("flag-thresholds") [] => {
-                    flags: rest.iter()
-                                .map(|v| v.split_once('=').ok_or_else(|| ErrorKind::MalformedDesc("Header flags are malformed".to_owned())))
-                                .map_ok(|(k,v)| (k.to_owned(), v.to_owned()))
-                                .collect::<Result<HashMap<_,_>,_>>()?,
+                    flag_thresholds: FlagThresholds::parse(rest)?,
                 },
                 uniq("fingerprint") [fingerprint] => {
                     fingerprint: fingerprint.to_string(),
                 },
+                opt("bandwidth-file-digest") [] => {
+                    bandwidth_file_digest: rest.map(|digests| digests.join(" ")),
+                },
             }
         })
     }
 }
 
+/// Thresholds used by the bridge authority to assign flags. Thresholds not present in the
+/// document, or unknown to this crate, are left to `None`.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct FlagThresholds {
+    pub stable_uptime: Option<u64>,
+    pub stable_mtbf: Option<u64>,
+    pub fast_speed: Option<u64>,
+    /// Weighted fractional uptime, in percent
+    pub guard_wfu: Option<f64>,
+    pub guard_tk: Option<u64>,
+    pub guard_bw_inc_exits: Option<u64>,
+    pub guard_bw_exc_exits: Option<u64>,
+    pub enough_mtbf: Option<u64>,
+}
+
+impl FlagThresholds {
+    fn parse(values: &[&str]) -> Result<Self, Error> {
+        let mut thresholds = FlagThresholds::default();
+        for value in values {
+            let (key, value) = value
+                .split_once('=')
+                .ok_or_else(|| ErrorKind::MalformedDesc("Header flags are malformed".to_owned()))?;
+            match key {
+                "stable-uptime" => thresholds.stable_uptime = Some(value.parse()?),
+                "stable-mtbf" => thresholds.stable_mtbf = Some(value.parse()?),
+                "fast-speed" => thresholds.fast_speed = Some(value.parse()?),
+                "guard-wfu" => {
+                    thresholds.guard_wfu =
+                        Some(value.trim_end_matches('%').parse().map_err(|_| {
+                            ErrorKind::MalformedDesc("invalid guard-wfu".to_owned())
+                        })?)
+                }
+                "guard-tk" => thresholds.guard_tk = Some(value.parse()?),
+                "guard-bw-inc-exits" => thresholds.guard_bw_inc_exits = Some(value.parse()?),
+                "guard-bw-exc-exits" => thresholds.guard_bw_exc_exits = Some(value.parse()?),
+                "enough-mtbf" => thresholds.enough_mtbf = Some(value.parse()?),
+                _ => (),
+            }
+        }
+        Ok(thresholds)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Builder)]
 pub struct NetworkStatus {
     pub nickname: String,
@@ -51,6 +108,8 @@ pub struct NetworkStatus {
     #[builder(setter(custom), default)]
     pub addresses: Vec<SocketAddr>,
     pub flags: Vec<String>,
+    /// Bandwidth from the `w` line, 0 if there is none
+    #[builder(default)]
     pub bandwidth: u64,
     #[builder(setter(custom))]
     pub policies: Vec<Policy>,
@@ -80,7 +139,7 @@ pub enum Policy {
     Reject(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct BridgeNetworkStatus {
     pub header: Header,
     pub network_status: Vec<NetworkStatus>,
@@ -88,124 +147,279 @@ pub struct BridgeNetworkStatus {
 
 impl BridgeNetworkStatus {
     pub fn parse(input: &str, version: (u32, u32)) -> Result<Self, Error> {
-        use crate::descriptor::nom_combinators::*;
+        check_version(version)?;
 
-        if version.0 != 1 || version.1 > 2 {
-            return Err(ErrorKind::UnsupportedDesc(format!(
-                "bridge-network-status v{}.{} is not supported",
-                version.0, version.1
-            ))
-            .into());
+        // the header ends where the first network status begins
+        let header_len = input
+            .match_indices("\nr ")
+            .next()
+            .map_or(input.len(), |(idx, _)| idx + 1);
+        let (header, body) = input.split_at(header_len);
+        let header = Header::parse(header)?;
+
+        let mut parser = StatusParser::default();
+        for line in body.lines() {
+            parser.line(line)?;
         }
 
-        let header = Header::parse(&format!(
-            "{}\n",
-            input.lines().take(3).collect::<Vec<_>>().join("\n")
-        ))?;
-
-        let mut network_status = Vec::new();
-        let mut first = true;
-
-        let builder = input.lines().skip(3).fold(
-            Ok(NetworkStatusBuilder::default()),
-            |acc, line| -> Result<NetworkStatusBuilder, Error> {
-                let mut builder = acc?;
-                match parse_line(line)? {
-                    ("r", params) => {
-                        if params.len() < 8 {
-                            return Err(Error::Collector(ErrorKind::MalformedDesc(
-                                "r lines need at least 8 parameters".to_owned(),
-                            )));
-                        }
-
-                        match builder.build() {
-                            Ok(net) => {
-                                network_status.push(net);
-                                builder = NetworkStatusBuilder::default();
-                            }
-                            Err(err) => {
-                                if !first {
-                                    return Err(Error::NetworkStatus(err));
-                                }
-                            }
-                        }
-                        first = false;
-
-                        Ok(builder
-                            .nickname(params[0].to_string())
-                            .identity(params[1].to_string())
-                            .digest(params[2].to_string())
-                            .publication(date(&format!("{} {}", params[3], params[4]))?.1)
-                            .ipv4(params[5].parse()?)
-                            .or_port(params[6].parse()?)
-                            .dir_port(params[7].parse()?)
-                            .to_owned())
-                    }
-                    ("a", params) => {
-                        if params.len() == 0 {
-                            return Err(Error::Collector(ErrorKind::MalformedDesc(
-                                "a lines need at least 1 parameters".to_owned(),
-                            )));
-                        }
-                        Ok(builder.addresses(params[0].parse()?))
-                    }
-                    ("s", params) => Ok(builder
-                        .flags(params.iter().map(|elem| elem.to_string()).collect())
-                        .to_owned()),
-                    ("w", params) => {
-                        if params.len() == 0 {
-                            return Err(Error::Collector(ErrorKind::MalformedDesc(
-                                "w lines need at least 1 parameters".to_owned(),
-                            )));
-                        }
-                        Ok(builder
-                            .bandwidth(
-                                params[0]
-                                    .split_once('=')
-                                    .ok_or_else(|| {
-                                        ErrorKind::MalformedDesc("Bandwidth malformed".to_owned())
-                                    })?
-                                    .1
-                                    .parse()?,
-                            )
-                            .to_owned())
-                    }
-                    ("p", params) => {
-                        if params.len() < 2 {
-                            return Err(Error::Collector(ErrorKind::MalformedDesc(
-                                "p lines need at least 2 parameters".to_owned(),
-                            )));
-                        }
-                        let pol = match params[0] {
-                            "accept" => Policy::Accept(params[1].to_owned()),
-                            "reject" => Policy::Reject(params[1].to_owned()),
-                            any => {
-                                return Err(Error::Collector(ErrorKind::MalformedDesc(format!(
-                                    "{} is not a valid netywork policy",
-                                    any
-                                ))));
-                            }
-                        };
-                        Ok(builder.policies(pol))
-                    }
-                    // handle empty line
-                    ("", _) => Ok(builder),
-                    (any, _) => Err(Error::Collector(ErrorKind::MalformedDesc(format!(
-                        "Lines starting with \"{}\" are not valid",
-                        any
-                    )))),
-                }
-            },
-        )?;
+        Ok(BridgeNetworkStatus {
+            header,
+            network_status: parser.finish()?,
+        })
+    }
 
-        //build the last network status parsed
-        network_status.push(builder.build()?);
+    /// Parse a document one line at a time, without holding it all in memory. `lines` must not
+    /// contain line endings, like what [`BufRead::lines`](std::io::BufRead::lines) returns.
+    pub fn parse_from_lines(
+        lines: impl IntoIterator<Item = Result<String, std::io::Error>>,
+        version: (u32, u32),
+    ) -> Result<Self, Error> {
+        check_version(version)?;
+
+        let mut lines = lines.into_iter();
+        // the header is small, buffer it until the first network status begins
+        let mut header = String::new();
+        let mut first_status = None;
+        for line in lines.by_ref() {
+            let line = line?;
+            if line.starts_with("r ") {
+                first_status = Some(line);
+                break;
+            }
+            header.push_str(&line);
+            header.push('\n');
+        }
+        let header = Header::parse(&header)?;
+
+        let mut parser = StatusParser::default();
+        if let Some(line) = first_status {
+            parser.line(&line)?;
+        }
+        for line in lines {
+            parser.line(&line?)?;
+        }
 
         Ok(BridgeNetworkStatus {
             header,
-            network_status,
+            network_status: parser.finish()?,
         })
     }
+
+    /// Whether this status was generated by the authority with fingerprint `authority_fp`,
+    /// compared case-insensitively.
+    pub fn was_generated_by(&self, authority_fp: &str) -> bool {
+        self.header
+            .authority_fingerprint()
+            .eq_ignore_ascii_case(authority_fp)
+    }
+
+    /// Get the bridges having `flag`, compared case-insensitively.
+    pub fn bridges_with_flag(&self, flag: &str) -> Vec<&NetworkStatus> {
+        self.network_status
+            .iter()
+            .filter(|status| status.flags.iter().any(|f| f.eq_ignore_ascii_case(flag)))
+            .collect()
+    }
+
+    /// Get the bridges having the `Guard` flag.
+    pub fn guard_bridges(&self) -> Vec<&NetworkStatus> {
+        self.bridges_with_flag("Guard")
+    }
+
+    /// Get the bridges having the `Stable` flag.
+    pub fn stable_bridges(&self) -> Vec<&NetworkStatus> {
+        self.bridges_with_flag("Stable")
+    }
+
+    /// Get the bridges having the `Fast` flag.
+    pub fn fast_bridges(&self) -> Vec<&NetworkStatus> {
+        self.bridges_with_flag("Fast")
+    }
+
+    /// Get the bridges having the `Running` flag.
+    pub fn running_bridges(&self) -> Vec<&NetworkStatus> {
+        self.bridges_with_flag("Running")
+    }
+
+    /// Number of bridges in this status.
+    pub fn total_bridges(&self) -> usize {
+        self.network_status.len()
+    }
+
+    /// Number of bridges having the `Running` flag.
+    pub fn active_bridges(&self) -> usize {
+        self.running_bridges().len()
+    }
+
+    /// Sum of the bandwidth of all bridges.
+    pub fn total_bandwidth(&self) -> u64 {
+        self.network_status
+            .iter()
+            .map(|status| status.bandwidth)
+            .sum()
+    }
+
+    /// Average bandwidth of the bridges having the `Running` flag, 0 if there are none.
+    pub fn mean_bandwidth(&self) -> f64 {
+        let running = self.running_bridges();
+        if running.is_empty() {
+            return 0.0;
+        }
+        let total: u64 = running.iter().map(|status| status.bandwidth).sum();
+        total as f64 / running.len() as f64
+    }
+
+    /// Count how many bridges have each flag.
+    pub fn flag_distribution(&self) -> HashMap<&str, usize> {
+        let mut distribution = HashMap::new();
+        for flag in self.network_status.iter().flat_map(|status| &status.flags) {
+            *distribution.entry(flag.as_str()).or_insert(0) += 1;
+        }
+        distribution
+    }
+
+    /// Combine two status documents, such as ones published after an authority restart, into
+    /// one containing the bridges of both. Bridges listed in both documents are taken from the
+    /// one published last, which also provides the header. Bridges are sorted by identity.
+    pub fn merge(a: Self, b: Self) -> Self {
+        let (older, newer) = if a.header.published_timestamp > b.header.published_timestamp {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        let mut network_status: BTreeMap<String, NetworkStatus> = BTreeMap::new();
+        for status in older.network_status.into_iter().chain(newer.network_status) {
+            network_status.insert(status.identity.clone(), status);
+        }
+        BridgeNetworkStatus {
+            header: newer.header,
+            network_status: network_status.into_values().collect(),
+        }
+    }
+
+    /// Parse multiple documents concatenated without their `@type` annotation, each starting
+    /// with a `network-status-version` line, or a `published` line for documents without one.
+    ///
+    /// Documents each preceded by their own `@type` line are already split by
+    /// [`FileReader`](crate::descriptor::file_reader::FileReader), and don't need this.
+    pub fn parse_all(input: &str, version: (u32, u32)) -> Result<Vec<Self>, Error> {
+        split_on_optional_header(input, Some("network-status-version"), "published")
+            .into_iter()
+            .map(|doc| BridgeNetworkStatus::parse(doc, version))
+            .collect()
+    }
+}
+
+fn check_version(version: (u32, u32)) -> Result<(), Error> {
+    if version.0 != 1 || version.1 > 2 {
+        return Err(ErrorKind::UnsupportedDesc(format!(
+            "bridge-network-status v{}.{} is not supported",
+            version.0, version.1
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Build network statuses from the lines following the header, one line at a time.
+#[derive(Default)]
+struct StatusParser {
+    network_status: Vec<NetworkStatus>,
+    builder: NetworkStatusBuilder,
+    /// Whether a `r` line was seen, and `builder` holds a network status
+    in_status: bool,
+}
+
+impl StatusParser {
+    fn line(&mut self, line: &str) -> Result<(), Error> {
+        use crate::descriptor::nom_combinators::*;
+
+        let mut builder = std::mem::take(&mut self.builder);
+        self.builder = match parse_line(line)? {
+            ("r", params) => {
+                if params.len() < 8 {
+                    return Err(Error::Collector(ErrorKind::MalformedDesc(
+                        "r lines need at least 8 parameters".to_owned(),
+                    )));
+                }
+
+                if self.in_status {
+                    self.network_status.push(builder.build()?);
+                }
+                self.in_status = true;
+
+                NetworkStatusBuilder::default()
+                    .nickname(params[0].to_string())
+                    .identity(params[1].to_string())
+                    .digest(params[2].to_string())
+                    .publication(date(&format!("{} {}", params[3], params[4]))?.1)
+                    .ipv4(params[5].parse()?)
+                    .or_port(all_consuming(port_number)(params[6])?.1)
+                    .dir_port(all_consuming(port_number)(params[7])?.1)
+                    .to_owned()
+            }
+            ("a", params) => {
+                if params.is_empty() {
+                    return Err(Error::Collector(ErrorKind::MalformedDesc(
+                        "a lines need at least 1 parameters".to_owned(),
+                    )));
+                }
+                builder.addresses(params[0].parse()?)
+            }
+            ("s", params) => {
+                builder.flags(params.iter().map(|elem| elem.to_string()).collect());
+                builder
+            }
+            ("w", params) => {
+                if params.is_empty() {
+                    return Err(Error::Collector(ErrorKind::MalformedDesc(
+                        "w lines need at least 1 parameters".to_owned(),
+                    )));
+                }
+                builder.bandwidth(
+                    params[0]
+                        .split_once('=')
+                        .ok_or_else(|| ErrorKind::MalformedDesc("Bandwidth malformed".to_owned()))?
+                        .1
+                        .parse()?,
+                );
+                builder
+            }
+            ("p", params) => {
+                if params.len() < 2 {
+                    return Err(Error::Collector(ErrorKind::MalformedDesc(
+                        "p lines need at least 2 parameters".to_owned(),
+                    )));
+                }
+                let pol = match params[0] {
+                    "accept" => Policy::Accept(params[1].to_owned()),
+                    "reject" => Policy::Reject(params[1].to_owned()),
+                    any => {
+                        return Err(Error::Collector(ErrorKind::MalformedDesc(format!(
+                            "{} is not a valid netywork policy",
+                            any
+                        ))));
+                    }
+                };
+                builder.policies(pol)
+            }
+            // handle empty line
+            ("", _) => builder,
+            (any, _) => {
+                return Err(Error::Collector(ErrorKind::MalformedDesc(format!(
+                    "Lines starting with \"{}\" are not valid",
+                    any
+                ))))
+            }
+        };
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<NetworkStatus>, Error> {
+        //build the last network status parsed
+        self.network_status.push(self.builder.build()?);
+        Ok(self.network_status)
+    }
 }
 
 fn parse_line(input: &str) -> Result<(&str, Vec<&str>), Error> {
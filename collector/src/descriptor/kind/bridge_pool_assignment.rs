@@ -2,13 +2,16 @@ use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
+use crate::descriptor::Fingerprint;
 use crate::error::{Error, ErrorKind};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BridgePoolAssignment {
     pub timestamp: DateTime<Utc>,
-    pub data: BTreeMap<String, (String, HashMap<String, String>)>,
+    #[serde(with = "assignment_format")]
+    pub data: BTreeMap<Fingerprint, (String, HashMap<String, String>)>,
 }
 
 impl BridgePoolAssignment {
@@ -22,22 +25,48 @@ impl BridgePoolAssignment {
             ))
             .into());
         }
-        let (i, _) = t(tag("bridge-pool-assignment ")(input))?;
-        let (i, timestamp) = date(i)?;
-        let (i, _) = t(line_ending(i))?;
+        let (i, header) = take_line(input)?;
+        let (_, (_, timestamp)) =
+            all_consuming(tuple((tag("bridge-pool-assignment "), date)))(header)?;
 
-        let mut it = iterator(i, tuple((fingerprint, space1, word, kv_space, line_ending)));
+        let mut it = iterator(
+            i,
+            map_parser(
+                take_line,
+                all_consuming(tuple((fingerprint, space1, word, kv_space))),
+            ),
+        );
 
-        let data = it.fold(BTreeMap::new(), |mut data, (fp, _, pool, kv, _)| {
-            data.insert(fp.to_owned(), (pool.to_owned(), kv));
-            data
-        });
+        let data = it
+            .map(|(fp, _, pool, kv)| Ok((fp.parse()?, (pool.to_owned(), kv))))
+            .collect::<Result<_, Error>>()?;
 
         let (i, _) = it.finish()?;
         t(eof(i))?;
 
         Ok(BridgePoolAssignment { timestamp, data })
     }
+
+    /// Iterate over `(fingerprint, pool, metadata)` of each assigned bridge, ordered by
+    /// fingerprint.
+    pub fn iter(&self) -> impl Iterator<Item = (&Fingerprint, &str, &HashMap<String, String>)> {
+        self.data
+            .iter()
+            .map(|(fp, (pool, meta))| (fp, pool.as_str(), meta))
+    }
+
+    /// Iterate over the fingerprint of each assigned bridge.
+    pub fn bridges(&self) -> impl Iterator<Item = &Fingerprint> {
+        self.data.keys()
+    }
+
+    /// Get the pool of each bridge, indexed by fingerprint, dropping metadata.
+    pub fn into_assignment_map(self) -> HashMap<Fingerprint, String> {
+        self.data
+            .into_iter()
+            .map(|(fp, (pool, _meta))| (fp, pool))
+            .collect()
+    }
 }
 
 impl Ord for BridgePoolAssignment {
@@ -51,3 +80,103 @@ impl PartialOrd for BridgePoolAssignment {
         Some(self.cmp(other))
     }
 }
+
+mod assignment_format {
+    //! (de)serialize assignments as `{ "pool": "...", "meta": { ... } }` instead of a tuple
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::descriptor::Fingerprint;
+
+    type Assignments = BTreeMap<Fingerprint, (String, HashMap<String, String>)>;
+
+    #[derive(Serialize)]
+    struct AssignmentRef<'a> {
+        pool: &'a str,
+        meta: &'a HashMap<String, String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Assignment {
+        pool: String,
+        #[serde(default)]
+        meta: HashMap<String, String>,
+    }
+
+    pub fn serialize<S: Serializer>(data: &Assignments, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(
+            data.iter()
+                .map(|(fp, (pool, meta))| (fp, AssignmentRef { pool, meta })),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Assignments, D::Error> {
+        let data = BTreeMap::<Fingerprint, Assignment>::deserialize(d)?;
+        Ok(data
+            .into_iter()
+            .map(|(fp, assignment)| (fp, (assignment.pool, assignment.meta)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serde_round_trip() {
+        let document = "bridge-pool-assignment 2022-07-17 18:07:19
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4 ip=4 blocklist=ru
+00782946f4c54ce1d028f21e541ef8440ecaa0ee moat transport=obfs4 ip=4
+";
+        let parsed = BridgePoolAssignment::parse(document, (1, 0)).unwrap();
+        assert_eq!(parsed.data.len(), 2);
+
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert!(json.contains(r#""pool":"email""#));
+        let deserialized: BridgePoolAssignment = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, deserialized);
+    }
+
+    #[test]
+    fn test_crlf() {
+        let document = "bridge-pool-assignment 2022-07-17 18:07:19\r
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4 ip=4 blocklist=ru\r
+00782946f4c54ce1d028f21e541ef8440ecaa0ee moat\r
+";
+        let parsed = BridgePoolAssignment::parse(document, (1, 0)).unwrap();
+        assert_eq!(
+            parsed,
+            BridgePoolAssignment::parse(&document.replace('\r', ""), (1, 0)).unwrap()
+        );
+        let (_, pool, meta) = parsed.iter().next().unwrap();
+        assert_eq!(pool, "email");
+        assert_eq!(meta.get("blocklist").map(String::as_str), Some("ru"));
+        let (_, pool, meta) = parsed.iter().nth(1).unwrap();
+        assert_eq!(pool, "moat");
+        assert!(meta.is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        let document = "bridge-pool-assignment 2022-07-17 18:07:19
+00782946f4c54ce1d028f21e541ef8440ecaa0ee moat transport=obfs4 ip=4
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4 ip=4 blocklist=ru
+";
+        let parsed = BridgePoolAssignment::parse(document, (1, 0)).unwrap();
+
+        let (fp, pool, meta) = parsed.iter().next().unwrap();
+        assert_eq!(fp.to_string(), "005fd4d7decbb250055b861579e6fdc79ad17bee");
+        assert_eq!(pool, "email");
+        assert_eq!(meta.get("blocklist").map(String::as_str), Some("ru"));
+
+        let email: Fingerprint = "005FD4D7DECBB250055B861579E6FDC79AD17BEE".parse().unwrap();
+        let moat: Fingerprint = "00782946f4c54ce1d028f21e541ef8440ecaa0ee".parse().unwrap();
+        assert_eq!(parsed.bridges().collect::<Vec<_>>(), [&email, &moat]);
+        assert_eq!(
+            parsed.into_assignment_map(),
+            HashMap::from([(email, "email".to_owned()), (moat, "moat".to_owned())])
+        );
+    }
+}
@@ -1,9 +1,14 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use chrono::{DateTime, Utc};
 
+use super::server_descriptor::{
+    decode_fingerprint, decode_ntor_key, parse_contact, proto_supports, valid_at, ContactInfo,
+    ExtraInfoDigest,
+};
 use super::utils::*;
 use crate::error::{Error, ErrorKind};
 
@@ -28,11 +33,14 @@ pub struct BridgeServerDescriptor {
     pub fingerprint: String,
     pub uptime: Option<u64>,
     pub bandwidth: (u64, u64, u64),
-    pub extra_info: Option<String>,
+    pub extra_info: Option<ExtraInfoDigest>,
     pub hidden_service: bool,
     pub contact: Option<String>,
     pub distribution_request: String,
     pub onion_key: Option<String>,
+    /// Certificate from the `ntor-onion-key-crosscert` line, with its sign bit. Sanitized
+    /// bridge descriptors don't include it.
+    pub ntor_onion_key_crosscert: Option<(String, i64)>,
     pub accept_reject: Vec<Network>,
     pub tunnelled: bool,
     pub router_sha256: Option<String>,
@@ -65,7 +73,7 @@ impl BridgeServerDescriptor {
                 uniq("router") [name, ip, port, _socks_port, _dir_port] => {
                     name: name.to_owned(),
                     ipv4: ip.parse()?,
-                    or_port: port.parse()?,
+                    or_port: all_consuming(port_number)(port)?.1,
                 },
                 opt("master-key-ed25519") [key] => {
                     master_key: key.map(|k| k.to_owned()),
@@ -88,11 +96,14 @@ impl BridgeServerDescriptor {
                 opt("uptime") [uptime] => {
                     uptime: uptime.map(|u| u.parse()).transpose()?,
                 },
-                uniq("bandwidth") [a, b, c] => {
-                    bandwidth: (a.parse()?, b.parse()?, c.parse()?),
+                uniq("bandwidth") [] => {
+                    bandwidth: parse_bandwidth(rest)?,
                 },
-                opt("extra-info-digest") [] => {
-                    extra_info: rest.map(|d| d.join(" ")),
+                opt("extra-info-digest") [sha1] => {
+                    extra_info: sha1.map(|sha1| ExtraInfoDigest {
+                        sha1: sha1.to_owned(),
+                        sha256: rest.and_then(|rest| rest.first()).map(|sha256| sha256.to_string()),
+                    }),
                 },
                 opt("hidden-service-dir") [] => {
                     hidden_service: rest.is_some(),
@@ -106,6 +117,13 @@ impl BridgeServerDescriptor {
                 opt("ntor-onion-key") [key] => {
                     onion_key: key.map(|k| k.to_owned()),
                 },
+                opt_cert("ntor-onion-key-crosscert") [certif, sign] => {
+                    ntor_onion_key_crosscert: certif.zip(sign)
+                        .map(|(certif, sign)| -> Result<_, Error> {
+                            Ok((cert_strict(certif)?.1.to_owned(), sign.parse()?))
+                        })
+                        .transpose()?,
+                },
                 opt("proto") [] => {
                     // TODO should reject when split_once fail
                     proto: rest.map(|r|
@@ -189,6 +207,82 @@ impl BridgeServerDescriptor {
         })
     }
 
+    /// Whether the bridge announced it is hibernating, and is not accepting connections.
+    pub fn is_hibernating(&self) -> bool {
+        self.hibernating
+    }
+
+    /// Whether the bridge is accepting connections.
+    pub fn is_active(&self) -> bool {
+        !self.hibernating
+    }
+
+    /// Whether the bridge supports `version` of subprotocol `proto`, according to its `proto`
+    /// line.
+    pub fn proto_supports(&self, proto: &str, version: u32) -> bool {
+        proto_supports(&self.proto, proto, version)
+    }
+
+    /// Whether this descriptor was published less than 48 hours ago.
+    pub fn is_recent(&self) -> bool {
+        self.is_valid_at(Utc::now())
+    }
+
+    /// Whether this descriptor was already published at `time`, and was still valid, being
+    /// published less than 48 hours before.
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        valid_at(self.timestamp, time)
+    }
+
+    /// Get all addresses the bridge accepts connections on: the one from the `router` line,
+    /// followed by the one from the `or-address` line, if any. The address from the `router`
+    /// line is always included.
+    pub fn or_addresses(&self) -> Vec<SocketAddr> {
+        let primary = SocketAddr::new(self.ipv4.into(), self.or_port);
+        let additional = self
+            .additional_address
+            .zip(self.additional_port)
+            .map(|(ip, port)| SocketAddr::new(ip, port));
+        std::iter::once(primary).chain(additional).collect()
+    }
+
+    /// Get the IPv4 addresses the bridge accepts connections on.
+    pub fn or_addresses_ipv4(&self) -> Vec<SocketAddr> {
+        self.or_addresses()
+            .into_iter()
+            .filter(SocketAddr::is_ipv4)
+            .collect()
+    }
+
+    /// Get the IPv6 addresses the bridge accepts connections on.
+    pub fn or_addresses_ipv6(&self) -> Vec<SocketAddr> {
+        self.or_addresses()
+            .into_iter()
+            .filter(SocketAddr::is_ipv6)
+            .collect()
+    }
+
+    /// Decode `fingerprint` to raw bytes.
+    pub fn identity_bytes(&self) -> Result<[u8; 20], Error> {
+        decode_fingerprint(&self.fingerprint)
+    }
+
+    /// Get `fingerprint` as lowercase hex, without spaces.
+    pub fn identity_hex(&self) -> String {
+        self.fingerprint.replace(' ', "").to_ascii_lowercase()
+    }
+
+    /// Extract structured information from `contact`, if there is one. See [`parse_contact`].
+    pub fn parsed_contact(&self) -> Option<ContactInfo> {
+        self.contact.as_deref().map(parse_contact)
+    }
+
+    /// Decode the ntor onion key stored in `onion_key`, checking it is a 32 bytes Curve25519
+    /// key.
+    pub fn ntor_onion_key_bytes(&self) -> Result<Option<[u8; 32]>, Error> {
+        self.onion_key.as_deref().map(decode_ntor_key).transpose()
+    }
+
     /// Create a dummy descriptor to allow range over BTree of BridgeServerDescriptor
     pub fn empty(timestamp: DateTime<Utc>) -> Self {
         BridgeServerDescriptor {
@@ -209,6 +303,7 @@ impl BridgeServerDescriptor {
             contact: None,
             distribution_request: String::new(),
             onion_key: None,
+            ntor_onion_key_crosscert: None,
             accept_reject: Vec::new(),
             tunnelled: false,
             router_sha256: None,
@@ -224,6 +319,23 @@ impl BridgeServerDescriptor {
     }
 }
 
+impl fmt::Display for BridgeServerDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BridgeServerDescriptor {{ name: {}, fp: {}, address: {}:{}, platform: {}, bandwidth: {}/{}/{} }}",
+            self.name,
+            self.fingerprint,
+            self.ipv4,
+            self.or_port,
+            self.platform,
+            self.bandwidth.0,
+            self.bandwidth.1,
+            self.bandwidth.2
+        )
+    }
+}
+
 impl Ord for BridgeServerDescriptor {
     fn cmp(&self, other: &Self) -> Ordering {
         self.timestamp
@@ -237,3 +349,88 @@ impl PartialOrd for BridgeServerDescriptor {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::VersionnedType;
+
+    #[test]
+    fn test_display() {
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let desc = BridgeServerDescriptor::parse(body, vt.version).unwrap();
+        let display = desc.to_string();
+        assert!(display.starts_with("BridgeServerDescriptor { name: Sr2Sponsor96Br114, "));
+        assert!(display.contains("address: 10.178.88.199:58247"));
+        assert!(display.contains(&format!("platform: {}", desc.platform)));
+        assert!(!display.contains('\n'));
+    }
+
+    #[test]
+    fn test_ntor_onion_key_crosscert() {
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        assert_eq!(
+            BridgeServerDescriptor::parse(body, vt.version)
+                .unwrap()
+                .ntor_onion_key_crosscert,
+            None
+        );
+
+        let cert = "-----BEGIN ED25519 CERT-----\nAQoABwqdAc93w4nAZHedTfUv7U6lW9Wv5HFniyFUqxWzLlIJPuTtAEFEx/7s5n1h\n-----END ED25519 CERT-----\n";
+        let body = body.replacen(
+            "reject 0.0.0.0/8:*\n",
+            &format!("ntor-onion-key-crosscert 1\n{cert}reject 0.0.0.0/8:*\n"),
+            1,
+        );
+        let desc = BridgeServerDescriptor::parse(&body, vt.version).unwrap();
+        let (parsed_cert, sign) = desc.ntor_onion_key_crosscert.unwrap();
+        assert_eq!(sign, 1);
+        assert!(parsed_cert.starts_with("-----BEGIN ED25519 CERT-----"));
+
+        let missing_cert = body.replacen(cert, "", 1);
+        assert!(BridgeServerDescriptor::parse(&missing_cert, vt.version).is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let desc = BridgeServerDescriptor::parse(body, vt.version).unwrap();
+        assert_eq!(
+            desc.identity_hex(),
+            "8c85fcf12fb3512ff4c58e33c0fcd9f7c5b9eef6"
+        );
+        assert_eq!(desc.identity_bytes().unwrap()[0], 0x8c);
+        assert!(BridgeServerDescriptor::empty(Utc::now())
+            .identity_bytes()
+            .is_err());
+    }
+
+    #[test]
+    fn test_or_addresses() {
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let desc = BridgeServerDescriptor::parse(body, vt.version).unwrap();
+        let ipv4: SocketAddr = "10.178.88.199:58247".parse().unwrap();
+        let ipv6: SocketAddr = "[fd9f:2e19:3bcf::ca:981b]:58247".parse().unwrap();
+        assert_eq!(desc.or_addresses(), [ipv4, ipv6]);
+        assert_eq!(desc.or_addresses_ipv4(), [ipv4]);
+        assert_eq!(desc.or_addresses_ipv6(), [ipv6]);
+
+        let desc = BridgeServerDescriptor::empty(Utc::now());
+        assert_eq!(desc.or_addresses().len(), 1);
+        assert!(desc.or_addresses_ipv6().is_empty());
+    }
+
+    #[test]
+    fn test_is_valid_at() {
+        let desc = BridgeServerDescriptor::empty(Utc::now() - chrono::Duration::hours(1));
+        assert!(desc.is_recent());
+        assert!(desc.is_valid_at(desc.timestamp + chrono::Duration::hours(47)));
+        assert!(!desc.is_valid_at(desc.timestamp + chrono::Duration::hours(48)));
+        assert!(!desc.is_valid_at(desc.timestamp - chrono::Duration::hours(1)));
+        assert!(!BridgeServerDescriptor::empty(Utc::now() - chrono::Duration::days(3)).is_recent());
+    }
+}
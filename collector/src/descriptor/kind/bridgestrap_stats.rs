@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
-use itertools::Itertools;
 
 use super::utils::*;
+use crate::descriptor::Fingerprint;
 use crate::error::{Error, ErrorKind};
 
 #[derive(Debug)]
@@ -33,6 +35,19 @@ impl Header {
 pub struct Stats {
     pub is_reachable: bool,
     pub fingerprint: String,
+    fingerprint_bytes: [u8; 20],
+}
+
+impl Stats {
+    /// Get the fingerprint as raw bytes. It is decoded once, while parsing.
+    pub fn fingerprint_bytes(&self) -> [u8; 20] {
+        self.fingerprint_bytes
+    }
+
+    /// Compare the fingerprint of this bridge with `other`, ignoring case.
+    pub fn fingerprint_matches(&self, other: &str) -> bool {
+        self.fingerprint.eq_ignore_ascii_case(other)
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +58,8 @@ pub struct BridgestrapStats {
 
 impl BridgestrapStats {
     pub fn parse(input: &str, version: (u32, u32)) -> Result<Self, Error> {
+        use crate::descriptor::nom_combinators::*;
+
         if version.0 != 1 || version.1 > 0 {
             return Err(ErrorKind::UnsupportedDesc(format!(
                 "bridgestrap-stats v{}.{} is not supported",
@@ -51,11 +68,11 @@ impl BridgestrapStats {
             .into());
         }
 
-        let header = Header::parse(&format!("{}\n", input.lines().take(2).join("\n")))?;
+        let (body, _) = tuple((take_line, take_line))(input)?;
+        let header = Header::parse(&input[..input.len() - body.len()])?;
 
-        let stats = input
+        let stats = body
             .lines()
-            .skip(2)
             .map(|line| {
                 let split = line.split(' ').collect::<Vec<_>>();
                 if split.len() < 3 {
@@ -66,6 +83,7 @@ impl BridgestrapStats {
                 } else {
                     Ok(Stats {
                         is_reachable: split[1].parse()?,
+                        fingerprint_bytes: split[2].parse::<Fingerprint>()?.0,
                         fingerprint: split[2].to_string(),
                     })
                 }
@@ -74,4 +92,107 @@ impl BridgestrapStats {
 
         Ok(BridgestrapStats { header, stats })
     }
+
+    /// Parse multiple documents concatenated without their `@type` annotation, each starting
+    /// with a `bridgestrap-stats-end` line.
+    pub fn parse_all(input: &str, version: (u32, u32)) -> Result<Vec<Self>, Error> {
+        split_on_header(input, "bridgestrap-stats-end")
+            .into_iter()
+            .map(|doc| BridgestrapStats::parse(doc, version))
+            .collect()
+    }
+
+    /// Index measurements by fingerprint, for repeated lookups.
+    pub fn build_index(&self) -> BridgestrapIndex {
+        BridgestrapIndex::from(self)
+    }
+}
+
+/// Reachability of each bridge measured in a [`BridgestrapStats`], indexed by fingerprint.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BridgestrapIndex {
+    reachable: HashMap<Fingerprint, bool>,
+}
+
+impl BridgestrapIndex {
+    /// Whether the bridge with `fingerprint` was reachable, or `None` if it wasn't measured.
+    /// The fingerprint is compared ignoring case.
+    pub fn is_reachable(&self, fingerprint: &str) -> Option<bool> {
+        let fingerprint: Fingerprint = fingerprint.parse().ok()?;
+        self.reachable.get(&fingerprint).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.reachable.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reachable.is_empty()
+    }
+}
+
+impl From<&BridgestrapStats> for BridgestrapIndex {
+    fn from(stats: &BridgestrapStats) -> Self {
+        BridgestrapIndex {
+            reachable: stats
+                .stats
+                .iter()
+                .map(|stat| (Fingerprint(stat.fingerprint_bytes), stat.is_reachable))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let input = "bridgestrap-stats-end 2023-01-14 22:15:03 (86400 s)
+bridgestrap-cached-requests 2
+bridgestrap-test true 005FD4D7DECBB250055B861579E6FDC79AD17BEE
+bridgestrap-test false 00782946F4C54CE1D028F21E541EF8440ECAA0EE
+";
+        let index = BridgestrapStats::parse(input, (1, 0))
+            .unwrap()
+            .build_index();
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index.is_reachable("005fd4d7decbb250055b861579e6fdc79ad17bee"),
+            Some(true)
+        );
+        assert_eq!(
+            index.is_reachable("00782946F4C54CE1D028F21E541EF8440ECAA0EE"),
+            Some(false)
+        );
+        assert_eq!(
+            index.is_reachable("0000000000000000000000000000000000000000"),
+            None
+        );
+        assert_eq!(index.is_reachable("not a fingerprint"), None);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let input = "bridgestrap-stats-end 2023-01-14 22:15:03 (86400 s)
+bridgestrap-cached-requests 1
+bridgestrap-test true 005FD4D7DECBB250055B861579E6FDC79AD17BEE
+";
+        let stats = BridgestrapStats::parse(input, (1, 0)).unwrap();
+        let stats = &stats.stats[0];
+        assert_eq!(stats.fingerprint_bytes()[..3], [0x00, 0x5f, 0xd4]);
+        assert_eq!(stats.fingerprint_bytes()[19], 0xee);
+        assert!(stats.fingerprint_matches("005fd4d7decbb250055b861579e6fdc79ad17bee"));
+        assert!(!stats.fingerprint_matches("00782946f4c54ce1d028f21e541ef8440ecaa0ee"));
+
+        for bad in [
+            "005FD4D7",
+            "005FD4D7DECBB250055B861579E6FDC79AD17BEEFF",
+            "X05FD4D7DECBB250055B861579E6FDC79AD17BEE",
+        ] {
+            let input = input.replace("005FD4D7DECBB250055B861579E6FDC79AD17BEE", bad);
+            assert!(BridgestrapStats::parse(&input, (1, 0)).is_err());
+        }
+    }
 }
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+
+use super::utils::*;
+use crate::error::{Error, ErrorKind};
+
+/// Legacy v1 directory, listing every router known to a directory authority.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Directory {
+    pub published: DateTime<Utc>,
+    /// Nickname of the directory authority which signed this directory
+    pub signed_directory: String,
+    pub router_entries: Vec<DirectoryRouterEntry>,
+}
+
+/// Router descriptor, as included in a [`Directory`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DirectoryRouterEntry {
+    pub name: String,
+    /// Hex encoded fingerprint, empty if the entry doesn't have a `fingerprint` line
+    pub fingerprint: String,
+    pub published: DateTime<Utc>,
+    pub platform: String,
+    /// Average, burst and observed bandwidth, in bytes per second
+    pub bandwidth: (u64, u64, u64),
+    /// Uptime in seconds, 0 if not announced
+    pub uptime: u64,
+}
+
+struct Header {
+    published: DateTime<Utc>,
+}
+
+impl Directory {
+    pub fn parse(input: &str, version: (u32, u32)) -> Result<Self, Error> {
+        if version.0 != 1 || version.1 != 0 {
+            return Err(ErrorKind::UnsupportedDesc(format!(
+                "directory v{}.{} is not supported",
+                version.0, version.1
+            ))
+            .into());
+        }
+
+        if !input.starts_with("signed-directory\n") {
+            return Err(ErrorKind::MalformedDesc(
+                "directory must start with `signed-directory`".to_owned(),
+            )
+            .into());
+        }
+
+        let (body, footer) = input
+            .find("\ndirectory-signature ")
+            .map(|idx| input.split_at(idx + 1))
+            .ok_or_else(|| {
+                ErrorKind::MalformedDesc("required line `directory-signature` missing".to_owned())
+            })?;
+        let signed_directory = footer
+            .lines()
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap_or_default()
+            .to_owned();
+
+        // the header ends where the first router entry begins
+        let header_len = body
+            .match_indices("\nrouter ")
+            .next()
+            .map_or(body.len(), |(idx, _)| idx + 1);
+        let (header, routers) = body.split_at(header_len);
+
+        let header = Header::parse(header)?;
+
+        let router_entries = split_on_header(routers, "router")
+            .into_iter()
+            .filter(|entry| !entry.is_empty())
+            .map(DirectoryRouterEntry::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Directory {
+            published: header.published,
+            signed_directory,
+            router_entries,
+        })
+    }
+}
+
+impl Header {
+    fn parse(input: &str) -> Result<Self, Error> {
+        let mut desc = descriptor_lines(input)?;
+        Ok(extract_desc! {
+            desc => Header rest {
+                uniq("published") [day, hour] => {
+                    published: parse_date(day, hour)?,
+                },
+            }
+        })
+    }
+}
+
+impl DirectoryRouterEntry {
+    fn parse(input: &str) -> Result<Self, Error> {
+        let mut desc = descriptor_lines(input)?;
+        Ok(extract_desc! {
+            desc => DirectoryRouterEntry rest {
+                uniq("router") [name, _address, _or_port, _socks_port, _dir_port] => {
+                    name: name.to_owned(),
+                },
+                opt("fingerprint") [] => {
+                    fingerprint: rest.map(|fp| fp.concat()).unwrap_or_default(),
+                },
+                uniq("published") [day, hour] => {
+                    published: parse_date(day, hour)?,
+                },
+                opt("platform") [] => {
+                    platform: rest.map(|platform| platform.join(" ")).unwrap_or_default(),
+                },
+                uniq("bandwidth") [] => {
+                    bandwidth: parse_bandwidth(rest)?,
+                },
+                opt("uptime") [uptime] => {
+                    uptime: uptime.map(str::parse).transpose()?.unwrap_or_default(),
+                },
+            }
+        })
+    }
+}
+
+fn parse_date(day: &str, hour: &str) -> Result<DateTime<Utc>, Error> {
+    use crate::descriptor::nom_combinators::date;
+
+    Ok(date(&format!("{} {}", day, hour))?.1)
+}
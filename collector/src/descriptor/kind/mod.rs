@@ -3,25 +3,36 @@ pub mod bridge_network_status;
 mod bridge_pool_assignment;
 mod bridge_server_descriptor;
 mod bridgestrap_stats;
+mod directory;
 mod server_descriptor;
 pub(crate) mod utils;
 
-pub use bridge_extra_info::BridgeExtraInfo;
+pub use bridge_extra_info::{
+    BridgeExtraInfo, BridgeExtraInfoByRelay, History, PaddingCounts, PluggableTransport,
+};
 pub use bridge_network_status::BridgeNetworkStatus;
 pub use bridge_pool_assignment::BridgePoolAssignment;
 pub use bridge_server_descriptor::BridgeServerDescriptor;
-pub use bridgestrap_stats::BridgestrapStats;
-pub use server_descriptor::{Microdescriptor, NetworkStatusMicrodescConsensus3, ServerDescriptor};
+pub use bridgestrap_stats::{BridgestrapIndex, BridgestrapStats};
+pub use directory::{Directory, DirectoryRouterEntry};
+pub use server_descriptor::{
+    parse_contact, ContactInfo, ExtraInfoDigest, Microdescriptor, NetworkStatusMicrodescConsensus3,
+    ServerDescriptor,
+};
 
+use std::borrow::Cow;
 use std::fmt;
+use std::io::Read;
 use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::descriptor::file_reader::skip_to_next_descriptor;
 use crate::error::{Error, ErrorKind};
 
 /// Type of a descriptor, unversionned
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
     BandwidthFile,
     BridgeExtraInfo,
@@ -66,6 +77,45 @@ impl Type {
         Type::Torperf,
     ];
 
+    /// Whether this type describes bridges, or is produced by bridge infrastructure.
+    pub fn is_bridge(&self) -> bool {
+        use Type::*;
+        matches!(
+            self,
+            BridgeExtraInfo
+                | BridgeNetworkStatus
+                | BridgePoolAssignment
+                | BridgeServerDescriptor
+                | BridgestrapStats
+        )
+    }
+
+    /// Whether this type describes public relays, or is produced by directory authorities.
+    pub fn is_relay(&self) -> bool {
+        use Type::*;
+        matches!(
+            self,
+            BandwidthFile
+                | DirKeyCertificate3
+                | Directory
+                | ExtraInfo
+                | Microdescriptor
+                | NetworkStatus2
+                | NetworkStatusConsensus3
+                | NetworkStatusMicrodescConsensus3
+                | NetworkStatusVote3
+                | ServerDescriptor
+        )
+    }
+
+    /// Whether this type is a consensus, either full or microdescriptor-based.
+    pub fn is_consensus(&self) -> bool {
+        matches!(
+            self,
+            Type::NetworkStatusConsensus3 | Type::NetworkStatusMicrodescConsensus3
+        )
+    }
+
     pub fn as_str(&self) -> &str {
         use Type::*;
         match self {
@@ -121,6 +171,17 @@ impl FromStr for Type {
     }
 }
 
+impl<'de> Deserialize<'de> for Type {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.parse()
+            .unwrap_or_else(|e: std::convert::Infallible| match e {}))
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.as_str())
@@ -189,20 +250,31 @@ impl<'de> Deserialize<'de> for VersionnedType {
     {
         use serde::de::Error;
         let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e: crate::error::Error| Error::custom(format!("invalid value: {e}")))
+    }
+}
+
+impl FromStr for VersionnedType {
+    type Err = Error;
+
+    /// Parse a type either as displayed (`@type bridge-extra-info 1.3`), or as found in the
+    /// index (`bridge-extra-info 1.3`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end();
+        let s = s.strip_prefix("@type ").unwrap_or(s);
+        let malformed = |msg: &str| ErrorKind::MalformedDesc(format!("{msg} in type \"{s}\""));
+
         let (ttype, version) = s
             .split_once(' ')
-            .ok_or_else(|| Error::custom("invalid value: missing space".to_owned()))?;
+            .ok_or_else(|| malformed("missing space"))?;
         let (major, minor) = version
             .split_once('.')
-            .ok_or_else(|| Error::custom("invalid value: missing dot".to_owned()))?;
+            .ok_or_else(|| malformed("missing dot"))?;
 
         let ttype = Type::from_str(ttype).unwrap();
-        let major = major
-            .parse()
-            .map_err(|_| Error::custom("invalid value: invalid major".to_owned()))?;
-        let minor = minor
-            .parse()
-            .map_err(|_| Error::custom("invalid value: invalid minor".to_owned()))?;
+        let major = major.parse().map_err(|_| malformed("invalid major"))?;
+        let minor = minor.parse().map_err(|_| malformed("invalid minor"))?;
 
         Ok(VersionnedType {
             ttype,
@@ -218,13 +290,13 @@ pub enum Descriptor {
     BridgePoolAssignment(BridgePoolAssignment),
     BridgeServerDescriptor(Box<BridgeServerDescriptor>),
     BridgestrapStats(Box<BridgestrapStats>),
+    Directory(Box<Directory>),
     Microdescriptor(Box<Microdescriptor>),
     NetworkStatusMicrodescConsensus3(Box<NetworkStatusMicrodescConsensus3>),
     ServerDescriptor(Box<ServerDescriptor>),
     /*
         BandwidthFile,
         DirKeyCertificate3,
-        Directory,
         ExtraInfo,
         NetworkStatus2,
         NetworkStatusConsensus3,
@@ -237,7 +309,9 @@ pub enum Descriptor {
 
 impl Descriptor {
     pub fn decode(raw_descriptor: &str) -> Result<Self, Error> {
-        let (buff, vt) = VersionnedType::parse(raw_descriptor).expect(&format!(""));
+        let (buff, vt) = VersionnedType::parse(raw_descriptor).map_err(|_| {
+            ErrorKind::MalformedDesc("descriptor doesn't start with an @type line".to_owned())
+        })?;
 
         match vt.ttype {
             Type::BridgeExtraInfo => Ok(Descriptor::BridgeExtraInfo(Box::new(
@@ -255,6 +329,9 @@ impl Descriptor {
             Type::BridgestrapStats => Ok(Descriptor::BridgestrapStats(Box::new(
                 BridgestrapStats::parse(buff, vt.version)?,
             ))),
+            Type::Directory => Ok(Descriptor::Directory(Box::new(Directory::parse(
+                buff, vt.version,
+            )?))),
             Type::Microdescriptor => Ok(Descriptor::Microdescriptor(Box::new(
                 Microdescriptor::parse(buff, vt.version)?,
             ))),
@@ -274,6 +351,55 @@ impl Descriptor {
         }
     }
 
+    /// Decode all descriptors in `bytes`, which can be xz or gzip compressed, as detected from
+    /// their magic bytes. Once decompressed, `bytes` must be plain text descriptors, each
+    /// starting with an `@type` line; tar archives are not supported here, use
+    /// [`FileReader`](crate::descriptor::file_reader::FileReader) for those.
+    pub fn decode_bytes(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        let bytes = decompress(bytes)?;
+        let mut input = std::str::from_utf8(&bytes)
+            .map_err(|e| ErrorKind::MalformedDesc(format!("descriptor is not valid utf-8: {e}")))?;
+        let mut descriptors = Vec::new();
+        while !input.is_empty() {
+            let next = skip_to_next_descriptor(input);
+            descriptors.push(Descriptor::decode(&input[..input.len() - next.len()])?);
+            input = next;
+        }
+        Ok(descriptors)
+    }
+
+    /// Get the type of this descriptor.
+    pub fn descriptor_type(&self) -> Type {
+        match self {
+            Descriptor::BridgeExtraInfo(_) => Type::BridgeExtraInfo,
+            Descriptor::BridgeNetworkStatus(_) => Type::BridgeNetworkStatus,
+            Descriptor::BridgePoolAssignment(_) => Type::BridgePoolAssignment,
+            Descriptor::BridgeServerDescriptor(_) => Type::BridgeServerDescriptor,
+            Descriptor::BridgestrapStats(_) => Type::BridgestrapStats,
+            Descriptor::Directory(_) => Type::Directory,
+            Descriptor::Microdescriptor(_) => Type::Microdescriptor,
+            Descriptor::NetworkStatusMicrodescConsensus3(_) => {
+                Type::NetworkStatusMicrodescConsensus3
+            }
+            Descriptor::ServerDescriptor(_) => Type::ServerDescriptor,
+        }
+    }
+
+    /// Whether this descriptor is about bridges. See [`Type::is_bridge`].
+    pub fn is_bridge(&self) -> bool {
+        self.descriptor_type().is_bridge()
+    }
+
+    /// Whether this descriptor is about public relays. See [`Type::is_relay`].
+    pub fn is_relay(&self) -> bool {
+        self.descriptor_type().is_relay()
+    }
+
+    /// Whether this descriptor is a consensus. See [`Type::is_consensus`].
+    pub fn is_consensus(&self) -> bool {
+        self.descriptor_type().is_consensus()
+    }
+
     pub fn bridge_extra_info(self) -> Result<BridgeExtraInfo, Self> {
         match self {
             Descriptor::BridgeExtraInfo(d) => Ok(*d),
@@ -309,12 +435,158 @@ impl Descriptor {
         }
     }
 
+    pub fn directory(self) -> Result<Directory, Self> {
+        match self {
+            Descriptor::Directory(d) => Ok(*d),
+            _ => Err(self),
+        }
+    }
+
     pub fn server_descriptor(self) -> Result<ServerDescriptor, Self> {
         match self {
             Descriptor::ServerDescriptor(d) => Ok(*d),
             _ => Err(self),
         }
     }
+
+    pub fn as_bridge_extra_info(&self) -> Option<&BridgeExtraInfo> {
+        match self {
+            Descriptor::BridgeExtraInfo(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_bridge_network_status(&self) -> Option<&BridgeNetworkStatus> {
+        match self {
+            Descriptor::BridgeNetworkStatus(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_bridge_pool_assignment(&self) -> Option<&BridgePoolAssignment> {
+        match self {
+            Descriptor::BridgePoolAssignment(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_bridge_server_descriptor(&self) -> Option<&BridgeServerDescriptor> {
+        match self {
+            Descriptor::BridgeServerDescriptor(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_bridgestrap_stats(&self) -> Option<&BridgestrapStats> {
+        match self {
+            Descriptor::BridgestrapStats(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_directory(&self) -> Option<&Directory> {
+        match self {
+            Descriptor::Directory(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_microdescriptor(&self) -> Option<&Microdescriptor> {
+        match self {
+            Descriptor::Microdescriptor(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_network_status_microdesc_consensus_3(
+        &self,
+    ) -> Option<&NetworkStatusMicrodescConsensus3> {
+        match self {
+            Descriptor::NetworkStatusMicrodescConsensus3(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_server_descriptor(&self) -> Option<&ServerDescriptor> {
+        match self {
+            Descriptor::ServerDescriptor(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Fingerprint and publication time identifying this descriptor, if it has them.
+    pub(crate) fn identity(&self) -> Option<(&str, DateTime<Utc>)> {
+        match self {
+            Descriptor::BridgeExtraInfo(d) => Some((&d.fingerprint, d.timestamp)),
+            Descriptor::BridgeNetworkStatus(d) => {
+                Some((&d.header.fingerprint, d.header.published_timestamp))
+            }
+            Descriptor::BridgePoolAssignment(d) => Some(("", d.timestamp)),
+            Descriptor::BridgeServerDescriptor(d) => Some((&d.fingerprint, d.timestamp)),
+            Descriptor::BridgestrapStats(d) => Some(("", d.header.timestamp)),
+            Descriptor::Directory(d) => Some((&d.signed_directory, d.published)),
+            Descriptor::ServerDescriptor(d) => Some((&d.fingerprint, d.timestamp)),
+            Descriptor::Microdescriptor(_) | Descriptor::NetworkStatusMicrodescConsensus3(_) => {
+                None
+            }
+        }
+    }
+}
+
+/// One line summary, such as `BridgeExtraInfo[fingerprint=AABB..., ts=2023-01-01T12:00:00Z]`.
+/// The fingerprint and timestamp are omitted for descriptors which don't have them.
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.descriptor_type())?;
+        match self.identity() {
+            Some(("", timestamp)) => {
+                write!(f, "[ts={}]", timestamp.format("%Y-%m-%dT%H:%M:%SZ"))
+            }
+            Some((fingerprint, timestamp)) => write!(
+                f,
+                "[fingerprint={}, ts={}]",
+                fingerprint,
+                timestamp.format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+macro_rules! impl_try_from_descriptor {
+    ($($variant:ident),*) => {
+        $(
+            impl TryFrom<Descriptor> for $variant {
+                type Error = Descriptor;
+
+                fn try_from(descriptor: Descriptor) -> Result<Self, Self::Error> {
+                    match descriptor {
+                        Descriptor::$variant(d) => Ok(*d),
+                        _ => Err(descriptor),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_descriptor!(
+    BridgeExtraInfo,
+    BridgeNetworkStatus,
+    BridgeServerDescriptor,
+    BridgestrapStats,
+    Directory,
+    Microdescriptor,
+    NetworkStatusMicrodescConsensus3,
+    ServerDescriptor
+);
+
+impl TryFrom<Descriptor> for BridgePoolAssignment {
+    type Error = Descriptor;
+
+    fn try_from(descriptor: Descriptor) -> Result<Self, Self::Error> {
+        descriptor.bridge_pool_assignment()
+    }
 }
 
 #[derive(Debug)]
@@ -323,17 +595,20 @@ pub(crate) struct DescriptorLine<'a> {
     pub values: Vec<&'a str>,
     pub cert: Option<&'a str>,
     pub line: u32,
+    /// Whether the line was prefixed with `opt`, as allowed by older versions of dir-spec.
+    pub is_optional: bool,
 }
 
 impl<'a> DescriptorLine<'a> {
     pub fn parse(input: &'a str) -> nom::IResult<&str, Self, nom::error::Error<&str>> {
         use crate::descriptor::nom_combinators::*;
         let (i, (mut name, mut values)) = sp_separated(input)?;
-        if name == "opt" && !values.is_empty() {
+        let is_optional = name == "opt" && !values.is_empty();
+        if is_optional {
             name = values.remove(0);
         }
         let (i, _) = line_ending(i)?;
-        let (i, cert) = opt(cert)(i)?;
+        let (i, cert) = try_cert(i)?;
 
         Ok((
             i,
@@ -342,15 +617,34 @@ impl<'a> DescriptorLine<'a> {
                 values,
                 cert,
                 line: 0,
+                is_optional,
             },
         ))
     }
 }
 
+/// Decompress `bytes` if they start with the magic bytes of xz or gzip.
+fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    const XZ_MAGIC: &[u8] = b"\xfd7zXZ\x00";
+    const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+    let mut decompressed = Vec::new();
+    if bytes.starts_with(XZ_MAGIC) {
+        xz2::read::XzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+    } else if bytes.starts_with(GZIP_MAGIC) {
+        flate2::read::GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+    } else {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    Ok(Cow::Owned(decompressed))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::descriptor::file_reader::FileReader;
 
+    use chrono::TimeZone;
+
     use futures::stream::{StreamExt, TryStreamExt};
 
     use super::*;
@@ -363,6 +657,113 @@ mod tests {
         desc
     }
 
+    #[test]
+    fn test_type_classification() {
+        for ttype in Type::ALL_TYPES {
+            // no type is both about bridges and relays
+            assert!(!(ttype.is_bridge() && ttype.is_relay()), "{ttype}");
+            if ttype.is_consensus() {
+                assert!(ttype.is_relay());
+            }
+        }
+        assert!(!Type::Unknown("bridge-foo".to_owned()).is_bridge());
+
+        let desc = Descriptor::BridgeExtraInfo(Box::new(BridgeExtraInfo::empty(Utc::now())));
+        assert_eq!(desc.descriptor_type(), Type::BridgeExtraInfo);
+        assert!(desc.is_bridge());
+        assert!(!desc.is_relay());
+        assert!(!desc.is_consensus());
+    }
+
+    #[test]
+    fn test_descriptor_display() {
+        let timestamp = Utc.ymd(2023, 1, 1).and_hms(12, 0, 0);
+        let mut extra_info = BridgeExtraInfo::empty(timestamp);
+        extra_info.fingerprint = "AABBCC".to_owned();
+        assert_eq!(
+            Descriptor::BridgeExtraInfo(Box::new(extra_info)).to_string(),
+            "BridgeExtraInfo[fingerprint=AABBCC, ts=2023-01-01T12:00:00Z]"
+        );
+
+        let assignment = BridgePoolAssignment {
+            timestamp,
+            data: Default::default(),
+        };
+        assert_eq!(
+            Descriptor::BridgePoolAssignment(assignment).to_string(),
+            "BridgePoolAssignment[ts=2023-01-01T12:00:00Z]"
+        );
+    }
+
+    #[test]
+    fn test_versionned_type_from_str() {
+        let expected = VersionnedType {
+            ttype: Type::BridgeExtraInfo,
+            version: (1, 3),
+        };
+        assert_eq!(expected, "@type bridge-extra-info 1.3".parse().unwrap());
+        assert_eq!(expected, "bridge-extra-info 1.3".parse().unwrap());
+        assert_eq!(expected, expected.to_string().parse().unwrap());
+        assert!("bridge-extra-info".parse::<VersionnedType>().is_err());
+        assert!("bridge-extra-info 1".parse::<VersionnedType>().is_err());
+        assert!("bridge-extra-info 1.x".parse::<VersionnedType>().is_err());
+
+        let json = serde_json::to_string(&expected).unwrap();
+        assert_eq!(json, r#""bridge-extra-info 1.3""#);
+        assert_eq!(expected, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn test_descriptor_line_opt_prefix() {
+        let (_, line) = DescriptorLine::parse("opt hibernating 1\n").unwrap();
+        assert_eq!(line.name, "hibernating");
+        assert_eq!(line.values, ["1"]);
+        assert!(line.is_optional);
+
+        let (_, line) = DescriptorLine::parse("hibernating 1\n").unwrap();
+        assert_eq!(line.name, "hibernating");
+        assert!(!line.is_optional);
+    }
+
+    #[test]
+    fn test_duplicate_line_reports_line_numbers() {
+        let raw = std::fs::read_to_string("tests/bridge_server_descriptor_test").unwrap();
+        let raw = raw.replace(
+            "uptime 3062540\n",
+            "uptime 3062540\npublished 2022-07-14 20:07:06\n",
+        );
+        let err = Descriptor::decode(&raw).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line `published` appeared multiple times (lines 6, 9)"));
+    }
+
+    #[test]
+    fn test_decode_bytes() {
+        use std::io::Write;
+
+        let plain = std::fs::read("tests/bridge_server_descriptor_ex").unwrap();
+        let descs = Descriptor::decode_bytes(&plain).unwrap();
+        assert!(descs.len() > 1);
+        assert!(descs
+            .iter()
+            .all(|desc| desc.descriptor_type() == Type::BridgeServerDescriptor));
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&plain).unwrap();
+        let xz = xz.finish().unwrap();
+        assert_eq!(Descriptor::decode_bytes(&xz).unwrap().len(), descs.len());
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&plain).unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(Descriptor::decode_bytes(&gz).unwrap().len(), descs.len());
+
+        assert!(Descriptor::decode_bytes(b"").unwrap().is_empty());
+        assert!(Descriptor::decode_bytes(b"not a descriptor\n").is_err());
+        assert!(Descriptor::decode_bytes(b"\x1f\x8bnot really gzip").is_err());
+    }
+
     #[tokio::test]
     async fn test_bridge_server_descriptor() {
         let res = read_test_file("tests/bridge_server_descriptor_test").await;
@@ -384,6 +785,11 @@ mod tests {
         println!("{:?}", res);
         assert_eq!(res.len(), 1);
         assert!(res[0].is_ok());
+        let desc = res[0].as_ref().unwrap().as_bridge_extra_info().unwrap();
+        let (_, duration, counts) = desc.padding_counts.as_ref().unwrap();
+        assert_eq!(*duration, 86400);
+        assert_eq!(counts.bin_size, Some(10000));
+        assert_eq!(counts.enabled_write_total, Some(17530000));
     }
 
     #[tokio::test]
@@ -392,6 +798,14 @@ mod tests {
         println!("{:?}", res);
         assert_eq!(res.len(), 1);
         assert!(res[0].is_ok());
+        let desc = res[0].as_ref().unwrap().as_server_descriptor().unwrap();
+        assert_eq!(
+            desc.extra_info,
+            ExtraInfoDigest {
+                sha1: "EE084318D5CC7FF7B437415282C1232A9408C223".to_owned(),
+                sha256: Some("LMWwqVSRj7Ktm6YjWkiwJrrwpCV3uvio9w0BzhFN8f4".to_owned()),
+            }
+        );
     }
 
     #[tokio::test]
@@ -399,6 +813,17 @@ mod tests {
         let mut res = read_test_file("tests/bridge_network_status_test").await;
         println!("{:?}", res);
         assert!(res[0].is_ok());
+        let desc = res[0].as_ref().unwrap();
+        assert!(desc.as_bridge_network_status().is_some());
+        assert!(desc.as_bridge_extra_info().is_none());
+        let thresholds = &desc
+            .as_bridge_network_status()
+            .unwrap()
+            .header
+            .flag_thresholds;
+        assert_eq!(thresholds.fast_speed, Some(102000));
+        assert_eq!(thresholds.guard_wfu, Some(98.0));
+        assert_eq!(thresholds.enough_mtbf, Some(1));
         let net = res
             .pop()
             .unwrap()
@@ -409,6 +834,187 @@ mod tests {
         assert_eq!(net.len(), 2);
     }
 
+    #[test]
+    fn test_bridge_network_status_from_lines() {
+        use std::io::BufRead;
+
+        let raw = std::fs::read_to_string("tests/bridge_network_status_large").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let from_str = BridgeNetworkStatus::parse(body, vt.version).unwrap();
+        let from_lines =
+            BridgeNetworkStatus::parse_from_lines(body.as_bytes().lines(), vt.version).unwrap();
+        assert_eq!(from_str, from_lines);
+        assert_eq!(from_lines.network_status.len(), 10);
+
+        let truncated = body.lines().take(3).map(|line| Ok(line.to_owned()));
+        assert!(BridgeNetworkStatus::parse_from_lines(truncated, vt.version).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bridge_network_status_large() {
+        let mut res = read_test_file("tests/bridge_network_status_large").await;
+        assert_eq!(res.len(), 1);
+        let status = res.pop().unwrap().unwrap().bridge_network_status().unwrap();
+        let net = &status.network_status;
+        assert_eq!(net.len(), 10);
+
+        assert_eq!(status.running_bridges().len(), 9);
+        assert_eq!(status.guard_bridges().len(), 3);
+        assert_eq!(status.stable_bridges().len(), 5);
+        assert_eq!(status.fast_bridges().len(), 5);
+        assert_eq!(status.flag_distribution()["Valid"], 9);
+        assert_eq!(status.total_bridges(), 10);
+        assert_eq!(status.active_bridges(), 9);
+        assert_eq!(status.total_bandwidth(), 5_005_507_721);
+        assert!((status.mean_bandwidth() - 5_005_507_721.0 / 9.0).abs() < 1e-3);
+        assert_eq!(net[3].flags, ["Running"]);
+
+        assert!(net[0].addresses.is_empty());
+        assert_eq!(
+            net[2].addresses,
+            ["[fd9f:2e19:3bcf::2]:40002".parse().unwrap()]
+        );
+        assert_eq!(net[5].addresses.len(), 2);
+
+        // doesn't fit in 32 bits
+        assert_eq!(net[8].bandwidth, 5_000_000_000);
+        // no w line
+        assert_eq!(net[9].nickname, "Bridge09");
+        assert_eq!(net[9].bandwidth, 0);
+    }
+
+    #[test]
+    fn test_bridge_network_status_parse_all() {
+        let raw = std::fs::read_to_string("tests/bridge_network_status_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let concatenated = format!("{body}{body}");
+
+        let docs = BridgeNetworkStatus::parse_all(&concatenated, vt.version).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0], docs[1]);
+        assert_eq!(docs[0].network_status.len(), 2);
+
+        let versioned = body.replacen("published ", "network-status-version 3\npublished ", 1);
+        let concatenated = format!("{versioned}{versioned}{body}");
+        let docs = BridgeNetworkStatus::parse_all(&concatenated, vt.version).unwrap();
+        assert_eq!(docs.len(), 3);
+        assert_eq!(docs[0].header.network_status_version, Some(3));
+        assert_eq!(docs[1].header.network_status_version, Some(3));
+        assert_eq!(docs[2].header.network_status_version, None);
+        assert_eq!(docs[1].network_status, docs[2].network_status);
+    }
+
+    #[test]
+    fn test_bridge_network_status_merge() {
+        let raw = std::fs::read_to_string("tests/bridge_network_status_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let older = BridgeNetworkStatus::parse(body, vt.version).unwrap();
+        // published an hour later, P0WP0W is replaced by another bridge, and Unnamed changed
+        let newer = body
+            .replace("published 2023-01-11 21:58:54", "published 2023-01-11 22:58:54")
+            .replace("w Bandwidth=57", "w Bandwidth=60")
+            .replacen(
+                "r P0WP0W AF/U197LslAFW4YVeeb9x5rRe+4 Evzhmw3xQB3fTCvBsjmRJOKA7Lg 2023-01-11 19:44:36 10.173.40.233 59324 0",
+                "r Bridge00 HJ5Z7DsBzQSs98TTAAAYe+PMQVw Um+OSxZ47/1S1+hZ9gmuzC9uZnU 2023-01-11 10:44:36 10.0.0.1 40000 0",
+                1,
+            );
+        let newer = BridgeNetworkStatus::parse(&newer, vt.version).unwrap();
+
+        let merged = BridgeNetworkStatus::merge(older.clone(), newer.clone());
+        assert_eq!(merged, BridgeNetworkStatus::merge(newer.clone(), older));
+        assert_eq!(merged.header, newer.header);
+        let nicknames: Vec<_> = merged
+            .network_status
+            .iter()
+            .map(|status| status.nickname.as_str())
+            .collect();
+        assert_eq!(nicknames, ["P0WP0W", "Unnamed", "Bridge00"]);
+        assert_eq!(merged.network_status[1].bandwidth, 60);
+    }
+
+    #[test]
+    fn test_bridge_network_status_flags() {
+        let raw = std::fs::read_to_string("tests/bridge_network_status_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let body = body.replacen(
+            "s Running V2Dir Valid",
+            "s Fast Guard Running Stable V2Dir Valid",
+            1,
+        );
+        let status = BridgeNetworkStatus::parse(&body, vt.version).unwrap();
+
+        assert_eq!(status.bridges_with_flag("guard").len(), 1);
+        assert_eq!(status.guard_bridges()[0].nickname, "P0WP0W");
+        assert_eq!(status.stable_bridges().len(), 1);
+        assert_eq!(status.fast_bridges().len(), 1);
+        assert_eq!(status.running_bridges().len(), 2);
+        assert!(status.bridges_with_flag("Exit").is_empty());
+        assert!(status.was_generated_by("ba44a889e64b93faa2b114e02c2a279a8555c533"));
+        assert_eq!(
+            status.header.authority_fingerprint(),
+            status.header.fingerprint
+        );
+        assert!(!status.was_generated_by("ba44a889e64b93faa2b114e02c2a279a8555c534"));
+
+        let distribution = status.flag_distribution();
+        assert_eq!(distribution["Running"], 2);
+        assert_eq!(distribution["Guard"], 1);
+        assert_eq!(distribution.get("Exit"), None);
+    }
+
+    #[test]
+    fn test_bridge_network_status_header_length() {
+        let raw = std::fs::read_to_string("tests/bridge_network_status_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let plain = BridgeNetworkStatus::parse(body, vt.version).unwrap();
+        assert_eq!(plain.header.network_status_version, None);
+
+        assert_eq!(plain.header.bandwidth_file_digest, None);
+
+        let extended = format!(
+            "network-status-version 3\nunknown-header-line 1\n{}",
+            body.replacen(
+                "\nr ",
+                "\nbandwidth-file-digest sha256=01234567890abcdef\nr ",
+                1
+            )
+        );
+        let extended = BridgeNetworkStatus::parse(&extended, vt.version).unwrap();
+        assert_eq!(extended.header.network_status_version, Some(3));
+        assert_eq!(
+            extended.header.bandwidth_file_digest.as_deref(),
+            Some("sha256=01234567890abcdef")
+        );
+        assert_eq!(
+            extended.header.published_timestamp,
+            plain.header.published_timestamp
+        );
+        assert_eq!(extended.network_status, plain.network_status);
+    }
+
+    #[test]
+    fn test_bridgestrap_stats_parse_all() {
+        let raw = std::fs::read_to_string("tests/bridge_strap_stats_test").unwrap();
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let concatenated = format!("{body}{body}");
+
+        let single = BridgestrapStats::parse(body, vt.version).unwrap();
+        let docs = BridgestrapStats::parse_all(&concatenated, vt.version).unwrap();
+        assert_eq!(docs.len(), 2);
+        for doc in docs {
+            assert_eq!(doc.header.timestamp, single.header.timestamp);
+            assert_eq!(doc.stats.len(), single.stats.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_try_from_descriptor() {
+        let mut res = read_test_file("tests/bridge_strap_stats_test").await;
+        let desc = res.pop().unwrap().unwrap();
+        let desc = BridgeExtraInfo::try_from(desc).unwrap_err();
+        assert!(BridgestrapStats::try_from(desc).is_ok());
+    }
+
     #[tokio::test]
     async fn test_bridgestrap_stats() {
         let mut res = read_test_file("tests/bridge_strap_stats_test").await;
@@ -423,4 +1029,50 @@ mod tests {
             .stats;
         assert_eq!(data.len(), 7);
     }
+
+    #[tokio::test]
+    async fn test_recover_from_malformed_descriptor() {
+        let valid = std::fs::read_to_string("tests/bridge_strap_stats_test").unwrap();
+        let corrupted = valid.replacen("bridgestrap-cached-requests", "bridgestrap-cached-req", 1);
+        let input = format!("garbage\n{valid}{corrupted}{valid}");
+
+        let res: Vec<_> = FileReader::read_from(std::io::Cursor::new(input), "in-memory")
+            .and_then(|s| async move { Descriptor::decode(&s) })
+            .collect()
+            .await;
+        assert_eq!(res.len(), 4);
+        assert!(res[0].is_err());
+        assert!(res[1].is_ok());
+        assert!(res[2].is_err());
+        assert!(res[3].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_directory() {
+        let mut res = read_test_file("tests/directory_test").await;
+        assert_eq!(res.len(), 1);
+        let directory = res.pop().unwrap().unwrap().directory().unwrap();
+        assert_eq!(directory.signed_directory, "moria1");
+        assert_eq!(
+            directory.published,
+            Utc.ymd(2005, 12, 21).and_hms(11, 37, 59)
+        );
+        assert_eq!(directory.router_entries.len(), 2);
+
+        let moria = &directory.router_entries[0];
+        assert_eq!(moria.name, "moria1");
+        assert_eq!(
+            moria.fingerprint,
+            "FFCB46DB1339DA84674C70D7CB586434C4370441"
+        );
+        assert_eq!(moria.platform, "Tor 0.1.1.11-alpha on Linux i686");
+        assert_eq!(moria.bandwidth, (512000, 1048576, 64920));
+        assert_eq!(moria.uptime, 1236522);
+
+        let peertje = &directory.router_entries[1];
+        assert_eq!(peertje.name, "peertje");
+        assert_eq!(peertje.fingerprint, "");
+        assert_eq!(peertje.published, Utc.ymd(2005, 12, 21).and_hms(10, 12, 44));
+        assert_eq!(peertje.uptime, 0);
+    }
 }
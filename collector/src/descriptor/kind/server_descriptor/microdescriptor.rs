@@ -90,6 +90,24 @@ impl Microdescriptor {
             }
         })
     }
+
+    /// Get the identity key of type `key_type`, compared case-insensitively.
+    pub fn id_for_type(&self, key_type: &str) -> Option<&str> {
+        self.id
+            .iter()
+            .find(|(ttype, _)| ttype.eq_ignore_ascii_case(key_type))
+            .map(|(_, id)| id.as_str())
+    }
+
+    /// Get the base64 encoded ed25519 identity key.
+    pub fn ed25519_id(&self) -> Option<&str> {
+        self.id_for_type("ed25519")
+    }
+
+    /// Get the base64 encoded digest of the RSA identity key.
+    pub fn rsa_id(&self) -> Option<&str> {
+        self.id_for_type("rsa1024")
+    }
 }
 
 mod tests {
@@ -126,6 +144,13 @@ EU7E8R+VxAEEOEg49if8/lwLVVMWkwkmh3ZZCvzLXE07M7x/pUrdAgMBAAE=
         };
 
         let parsed = Microdescriptor::parse(document, (1, 0)).unwrap();
-        assert_eq!(parsed, expected)
+        assert_eq!(parsed, expected);
+
+        assert_eq!(
+            parsed.ed25519_id(),
+            Some("H2XNSv4eCVNaW9WMo6GlYryaU20F3P+Xwbt2v+4mDm0")
+        );
+        assert_eq!(parsed.id_for_type("Ed25519"), parsed.ed25519_id());
+        assert_eq!(parsed.rsa_id(), None);
     }
 }
@@ -1,3 +1,10 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::descriptor::Fingerprint;
+use crate::error::{Error, ErrorKind};
+
 mod microdescriptor;
 mod network_status_microdesc_consensus_3;
 mod server_descriptor;
@@ -11,3 +18,195 @@ pub enum Network {
     Accept(String),
     Reject(String),
 }
+
+/// Digests of the extra-info document published along a server descriptor.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExtraInfoDigest {
+    /// Hex encoded SHA1 digest
+    pub sha1: String,
+    /// Base64 encoded SHA256 digest, only present in recent descriptors
+    pub sha256: Option<String>,
+}
+
+/// Contact information of a relay operator, extracted from a free-form `contact` line.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ContactInfo {
+    /// The whole `contact` line
+    pub text: String,
+    pub email: Option<String>,
+    pub url: Option<String>,
+    /// PGP key fingerprint, without any `0x` prefix
+    pub pgp_fingerprint: Option<String>,
+}
+
+/// Extract what looks like an email, url and PGP fingerprint from a `contact` line. This is
+/// best-effort: operators are free to write anything there. Both plain text
+/// (`John Doe <john@example.org>`) and the `email:`, `url:` and `pgp:` fields of the
+/// ContactInfo specification are understood.
+pub fn parse_contact(contact: &str) -> ContactInfo {
+    let mut info = ContactInfo {
+        text: contact.to_owned(),
+        ..ContactInfo::default()
+    };
+    for token in contact.split_whitespace() {
+        let token = token.trim_matches(|c| "<>()[],;\"'".contains(c));
+        let (key, value) = match token.split_once(':') {
+            Some((key, value)) => (key.to_ascii_lowercase(), value),
+            None => (String::new(), token),
+        };
+        match key.as_str() {
+            // the ContactInfo specification writes `@` as `[]`
+            "email" => {
+                info.email.get_or_insert_with(|| value.replace("[]", "@"));
+            }
+            "url" => {
+                info.url.get_or_insert_with(|| value.to_owned());
+            }
+            "pgp" => {
+                info.pgp_fingerprint
+                    .get_or_insert_with(|| value.trim_start_matches("0x").to_owned());
+            }
+            "http" | "https" => {
+                info.url.get_or_insert_with(|| token.to_owned());
+            }
+            "" | "mailto" if value.contains('@') && value.contains('.') => {
+                info.email.get_or_insert_with(|| value.to_owned());
+            }
+            "" if value.len() >= 18
+                && value.starts_with("0x")
+                && value[2..].bytes().all(|b| b.is_ascii_hexdigit()) =>
+            {
+                info.pgp_fingerprint
+                    .get_or_insert_with(|| value[2..].to_owned());
+            }
+            _ => (),
+        }
+    }
+    info
+}
+
+/// Check whether `version` of subprotocol `proto` is listed in a `proto` line, where versions
+/// are a comma-separated list of numbers and inclusive ranges, such as `1,3-5`.
+pub(crate) fn proto_supports(protos: &HashMap<String, String>, proto: &str, version: u32) -> bool {
+    let Some(versions) = protos.get(proto) else {
+        return false;
+    };
+    versions
+        .split(',')
+        .any(|range| match range.split_once('-') {
+            Some((low, high)) => match (low.parse::<u32>(), high.parse::<u32>()) {
+                (Ok(low), Ok(high)) => (low..=high).contains(&version),
+                _ => false,
+            },
+            None => range.parse() == Ok(version),
+        })
+}
+
+/// Whether a descriptor published at `published` is still valid at `time`. Descriptors are
+/// valid for 48 hours after their publication.
+pub(crate) fn valid_at(published: DateTime<Utc>, time: DateTime<Utc>) -> bool {
+    published <= time && time - published < Duration::hours(48)
+}
+
+/// Decode a fingerprint as found in `fingerprint` lines, hex encoded in groups of 4 characters
+/// separated by spaces.
+pub(crate) fn decode_fingerprint(fingerprint: &str) -> Result<[u8; 20], Error> {
+    Ok(fingerprint.replace(' ', "").parse::<Fingerprint>()?.0)
+}
+
+/// Decode a base64 encoded ntor onion key, which must be a 32 bytes Curve25519 key.
+pub(crate) fn decode_ntor_key(key: &str) -> Result<[u8; 32], Error> {
+    let bytes = base64::decode_config(key.trim_end_matches('='), base64::STANDARD_NO_PAD)
+        .map_err(|e| ErrorKind::MalformedDesc(format!("invalid ntor-onion-key: {e}")))?;
+    bytes.try_into().map_err(|bytes: Vec<u8>| {
+        ErrorKind::MalformedDesc(format!(
+            "ntor-onion-key must be 32 bytes long, got {}",
+            bytes.len()
+        ))
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_ntor_key() {
+        let key = decode_ntor_key("LxPfZiET1BkCMos/h85BI13ZQmU7Ft55Ao/ji9t2Ei4").unwrap();
+        assert_eq!(key[0], 0x2f);
+        assert_eq!(key[31], 0x2e);
+        assert_eq!(
+            decode_ntor_key("LxPfZiET1BkCMos/h85BI13ZQmU7Ft55Ao/ji9t2Ei4=").unwrap(),
+            key
+        );
+        assert!(decode_ntor_key("LxPfZiET1BkCMos/h85BI13ZQmU7Ft55Ao").is_err());
+        assert!(decode_ntor_key("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_parse_contact() {
+        let info = parse_contact("John Doe <john@example.org> URL:https://example.org PGP:AABBCC");
+        assert_eq!(
+            info,
+            ContactInfo {
+                text: "John Doe <john@example.org> URL:https://example.org PGP:AABBCC".to_owned(),
+                email: Some("john@example.org".to_owned()),
+                url: Some("https://example.org".to_owned()),
+                pgp_fingerprint: Some("AABBCC".to_owned()),
+            }
+        );
+
+        let info = parse_contact("email:tor[]example.org url:example.org ciissversion:2");
+        assert_eq!(info.email.as_deref(), Some("tor@example.org"));
+        assert_eq!(info.url.as_deref(), Some("example.org"));
+
+        let info = parse_contact("Jane (https://jane.example) 0x0123456789ABCDEF01");
+        assert_eq!(info.url.as_deref(), Some("https://jane.example"));
+        assert_eq!(info.pgp_fingerprint.as_deref(), Some("0123456789ABCDEF01"));
+        assert_eq!(info.email, None);
+
+        assert_eq!(parse_contact("nothing useful").email, None);
+    }
+
+    #[test]
+    fn test_decode_fingerprint() {
+        let bytes =
+            decode_fingerprint("1DD7 3BF4 237F 8C97 B374 59A6 4080 FF7A F37B 6757").unwrap();
+        assert_eq!(bytes[..2], [0x1d, 0xd7]);
+        assert_eq!(
+            decode_fingerprint("1dd73bf4237f8c97b37459a64080ff7af37b6757").unwrap(),
+            bytes
+        );
+        assert!(decode_fingerprint("1DD7 3BF4").is_err());
+        assert!(decode_fingerprint("").is_err());
+    }
+
+    #[test]
+    fn test_valid_at() {
+        use chrono::TimeZone;
+
+        let published = Utc.ymd(2022, 7, 26).and_hms(20, 0, 0);
+        assert!(valid_at(published, published));
+        assert!(valid_at(published, published + Duration::hours(47)));
+        assert!(!valid_at(published, published + Duration::hours(48)));
+        assert!(!valid_at(published, published - Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_proto_supports() {
+        let protos = HashMap::from([
+            ("Link".to_owned(), "1-5".to_owned()),
+            ("LinkAuth".to_owned(), "1,3".to_owned()),
+            ("Relay".to_owned(), "1-2,4".to_owned()),
+        ]);
+        assert!(proto_supports(&protos, "Link", 1));
+        assert!(proto_supports(&protos, "Link", 5));
+        assert!(!proto_supports(&protos, "Link", 6));
+        assert!(proto_supports(&protos, "LinkAuth", 3));
+        assert!(!proto_supports(&protos, "LinkAuth", 2));
+        assert!(proto_supports(&protos, "Relay", 4));
+        assert!(!proto_supports(&protos, "Relay", 3));
+        assert!(!proto_supports(&protos, "Cons", 1));
+    }
+}
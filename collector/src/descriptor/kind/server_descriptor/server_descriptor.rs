@@ -1,11 +1,15 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use chrono::{DateTime, Utc};
 
-use super::Network;
+use super::{
+    decode_fingerprint, decode_ntor_key, parse_contact, proto_supports, valid_at, ContactInfo,
+    ExtraInfoDigest, Network,
+};
 use crate::descriptor::kind::utils::*;
+use crate::descriptor::kind::DescriptorLine;
 use crate::error::{Error, ErrorKind};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -15,27 +19,43 @@ pub struct ServerDescriptor {
     pub name: String,
     pub ipv4: Ipv4Addr,
     pub or_port: u16,
+    /// First IPv6 address from `or-address` lines
     pub ipv6: Option<Ipv6Addr>,
     pub or_port_v6: Option<u16>,
-    pub identity_ed25519: String,
-    pub master_key_ed25519: String,
+    /// Additional addresses from all `or-address` lines, IPv4 or IPv6
+    pub or_addresses: Vec<SocketAddr>,
+    /// Ed25519 identity certificate, absent from descriptors of relays older than Tor 0.2.7
+    pub identity_ed25519: Option<String>,
+    pub master_key_ed25519: Option<String>,
     pub platform: String,
     pub proto: HashMap<String, String>,
     pub fingerprint: String,
     pub uptime: u64,
     pub bandwidth: (u64, u64, u64),
-    pub extra_info: String,
+    pub extra_info: ExtraInfoDigest,
     pub onion_key: String,
     pub signing_key: String,
-    pub onion_key_crosscert: String,
-    pub ntor_onion_key_crosscert: (String, i64),
+    pub onion_key_crosscert: Option<String>,
+    pub ntor_onion_key_crosscert: Option<(String, i64)>,
     pub hidden_service: bool,
     pub contact: Option<String>,
     pub ntor_onion_key: String,
     pub accept_reject: Vec<Network>,
-    pub router_sig_ed25519: String,
+    pub router_sig_ed25519: Option<String>,
     pub router_signature: String,
     pub tunnelled: bool,
+    pub cache_extra_info: bool,
+    pub family: Vec<String>,
+    pub allow_single_hop_exits: bool,
+    /// Version and time of the `overload-general` line, if the relay reported being overloaded
+    pub overload: Option<(u32, DateTime<Utc>)>,
+    /// Version, time, rate limit, burst limit, read and write overload counts of the
+    /// `overload-ratelimits` line. Tor publishes this line in extra-info documents, so it is
+    /// usually absent.
+    pub overload_ratelimits: Option<(u32, DateTime<Utc>, u64, u64, u64, u64)>,
+    /// Version and time of the `overload-fd-exhausted` line. Tor publishes this line in
+    /// extra-info documents, so it is usually absent.
+    pub overload_fd_exhausted: Option<(u32, DateTime<Utc>)>,
 }
 
 impl ServerDescriptor {
@@ -57,22 +77,22 @@ impl ServerDescriptor {
                 uniq("router") [name, ip, port] => {
                         name: name.to_owned(),
                         ipv4: ip.parse()?,
-                        or_port: port.parse()?,
+                        or_port: all_consuming(port_number)(port)?.1,
                 },
                 uniq("published") [day, hour] => {
                     timestamp: date(&format!("{} {}", day, hour))?.1,
                 },
-                opt("or-address") [address] => {
-                    ipv6: address.map(str::parse::<SocketAddrV6>).transpose()?
-                        .as_ref().map(SocketAddrV6::ip).copied(),
-                    or_port_v6: address.map(str::parse::<SocketAddrV6>).transpose()?
-                        .as_ref().map(SocketAddrV6::port),
+                multi("or-address") [] => {
+                    or_addresses: parse_or_addresses(&rest)?,
+                    // filled from or_addresses by with_first_ipv6
+                    ipv6: None,
+                    or_port_v6: None,
                 },
-                cert("identity-ed25519") [certif] => {
-                    identity_ed25519: certif.to_owned(),
+                opt_cert("identity-ed25519") [certif] => {
+                    identity_ed25519: certif.map(str::to_owned),
                 },
-                uniq("master-key-ed25519") [key] => {
-                    master_key_ed25519: key.to_owned(),
+                opt("master-key-ed25519") [key] => {
+                    master_key_ed25519: key.map(str::to_owned),
                 },
                 uniq("platform") [] => {
                     platform: rest.join(" "),
@@ -92,11 +112,14 @@ impl ServerDescriptor {
                 uniq("uptime") [uptime] => {
                     uptime: uptime.parse()?,
                 },
-                uniq("bandwidth") [a, b, c] => {
-                    bandwidth: (a.parse()?, b.parse()?, c.parse()?),
+                uniq("bandwidth") [] => {
+                    bandwidth: parse_bandwidth(rest)?,
                 },
-                uniq("extra-info-digest") [] => {
-                    extra_info: rest.join(" "),
+                uniq("extra-info-digest") [sha1] => {
+                    extra_info: ExtraInfoDigest {
+                        sha1: sha1.to_owned(),
+                        sha256: rest.first().map(|sha256| sha256.to_string()),
+                    },
                 },
                 cert("onion-key") [certif] => {
                     onion_key: certif.to_owned(),
@@ -104,11 +127,17 @@ impl ServerDescriptor {
                 cert("signing-key") [certif] => {
                     signing_key: certif.to_owned(),
                 },
-                cert("onion-key-crosscert") [certif] => {
-                    onion_key_crosscert: certif.to_owned(),
+                opt_cert("onion-key-crosscert") [certif] => {
+                    onion_key_crosscert: certif
+                        .map(|certif| -> Result<_, Error> { Ok(cert_strict(certif)?.1.to_owned()) })
+                        .transpose()?,
                 },
-                cert("ntor-onion-key-crosscert") [certif, num] => {
-                    ntor_onion_key_crosscert: (certif.to_owned(), num.parse()?),
+                opt_cert("ntor-onion-key-crosscert") [certif, num] => {
+                    ntor_onion_key_crosscert: certif.zip(num)
+                        .map(|(certif, num)| -> Result<_, Error> {
+                            Ok((cert_strict(certif)?.1.to_owned(), num.parse()?))
+                        })
+                        .transpose()?,
                 },
                 opt("hidden-service-dir") [] => {
                     hidden_service: rest.is_some(),
@@ -122,6 +151,18 @@ impl ServerDescriptor {
                 opt("tunnelled-dir-server") [] => {
                     tunnelled: rest.is_some(),
                 },
+                opt("caches-extra-info") [] => {
+                    cache_extra_info: rest.is_some(),
+                },
+                opt("family") [] => {
+                    family: rest.unwrap_or_default()
+                        .iter()
+                        .map(|i| (*i).to_owned())
+                        .collect(),
+                },
+                opt("allow-single-hop-exits") [] => {
+                    allow_single_hop_exits: rest.is_some(),
+                },
                 multi("accept", "reject") [] => {
                     accept_reject: {
                         rest.iter().map(|e| match e.name {
@@ -144,14 +185,92 @@ impl ServerDescriptor {
                         .collect::<Result<Vec<_>, Error>>()?
                     },
                 },
-                uniq("router-sig-ed25519") [sig] => {
-                    router_sig_ed25519: sig.to_owned(),
+                opt("router-sig-ed25519") [sig] => {
+                    router_sig_ed25519: sig.map(str::to_owned),
                 },
                 cert("router-signature") [certif] => {
-                    router_signature: certif.to_owned(),
+                    router_signature: cert_strict(certif)?.1.to_owned(),
+                },
+                opt("overload-general") [version, day, hour] => {
+                    overload: if let Some(version) = version {
+                        let date = date(&format!("{} {}", day.unwrap(), hour.unwrap()))?.1;
+                        Some((version.parse()?, date))
+                    } else {
+                        None
+                    },
+                },
+                opt("overload-ratelimits") [version, day, hour, rate, burst, read, write] => {
+                    overload_ratelimits: if let Some(version) = version {
+                        let date = date(&format!("{} {}", day.unwrap(), hour.unwrap()))?.1;
+                        Some((
+                            version.parse()?,
+                            date,
+                            rate.unwrap().parse()?,
+                            burst.unwrap().parse()?,
+                            read.unwrap().parse()?,
+                            write.unwrap().parse()?,
+                        ))
+                    } else {
+                        None
+                    },
+                },
+                opt("overload-fd-exhausted") [version, day, hour] => {
+                    overload_fd_exhausted: if let Some(version) = version {
+                        let date = date(&format!("{} {}", day.unwrap(), hour.unwrap()))?.1;
+                        Some((version.parse()?, date))
+                    } else {
+                        None
+                    },
                 },
             }
         })
+        .map(Self::with_first_ipv6)
+    }
+
+    /// Fill `ipv6` and `or_port_v6` from the first IPv6 address of `or_addresses`.
+    fn with_first_ipv6(mut self) -> Self {
+        if let Some(addr) = first_ipv6(&self.or_addresses) {
+            self.ipv6 = Some(*addr.ip());
+            self.or_port_v6 = Some(addr.port());
+        }
+        self
+    }
+
+    /// Whether the relay supports `version` of subprotocol `proto`, according to its `proto`
+    /// line.
+    pub fn proto_supports(&self, proto: &str, version: u32) -> bool {
+        proto_supports(&self.proto, proto, version)
+    }
+
+    /// Whether this descriptor was published less than 48 hours ago.
+    pub fn is_recent(&self) -> bool {
+        self.is_valid_at(Utc::now())
+    }
+
+    /// Whether this descriptor was already published at `time`, and was still valid, being
+    /// published less than 48 hours before.
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        valid_at(self.timestamp, time)
+    }
+
+    /// Decode `fingerprint` to raw bytes.
+    pub fn identity_bytes(&self) -> Result<[u8; 20], Error> {
+        decode_fingerprint(&self.fingerprint)
+    }
+
+    /// Get `fingerprint` as lowercase hex, without spaces.
+    pub fn identity_hex(&self) -> String {
+        self.fingerprint.replace(' ', "").to_ascii_lowercase()
+    }
+
+    /// Extract structured information from `contact`, if there is one. See [`parse_contact`].
+    pub fn parsed_contact(&self) -> Option<ContactInfo> {
+        self.contact.as_deref().map(parse_contact)
+    }
+
+    /// Decode `ntor_onion_key`, checking it is a 32 bytes Curve25519 key.
+    pub fn ntor_onion_key_bytes(&self) -> Result<[u8; 32], Error> {
+        decode_ntor_key(&self.ntor_onion_key)
     }
 
     /// Create a dummy descriptor to allow range over BTree of ServerDescriptor
@@ -163,29 +282,58 @@ impl ServerDescriptor {
             or_port: 0,
             ipv6: None,
             or_port_v6: None,
-            identity_ed25519: String::new(),
-            master_key_ed25519: String::new(),
+            or_addresses: Vec::new(),
+            identity_ed25519: None,
+            master_key_ed25519: None,
             platform: String::new(),
             proto: HashMap::new(),
             fingerprint: String::new(),
             uptime: 0,
             bandwidth: (0, 0, 0),
-            extra_info: String::new(),
+            extra_info: ExtraInfoDigest {
+                sha1: String::new(),
+                sha256: None,
+            },
             onion_key: String::new(),
             signing_key: String::new(),
-            onion_key_crosscert: String::new(),
-            ntor_onion_key_crosscert: (String::new(), 0),
+            onion_key_crosscert: None,
+            ntor_onion_key_crosscert: None,
             hidden_service: false,
             contact: None,
             ntor_onion_key: String::new(),
             accept_reject: Vec::new(),
-            router_sig_ed25519: String::new(),
+            router_sig_ed25519: None,
             router_signature: String::new(),
             tunnelled: false,
+            cache_extra_info: false,
+            family: Vec::new(),
+            allow_single_hop_exits: false,
+            overload: None,
+            overload_ratelimits: None,
+            overload_fd_exhausted: None,
         }
     }
 }
 
+fn parse_or_addresses(lines: &[DescriptorLine]) -> Result<Vec<SocketAddr>, Error> {
+    lines
+        .iter()
+        .map(|line| {
+            let address = line.values.first().ok_or_else(|| {
+                ErrorKind::MalformedDesc("missing parameters to or-address".to_owned())
+            })?;
+            Ok(address.parse()?)
+        })
+        .collect()
+}
+
+fn first_ipv6(addresses: &[SocketAddr]) -> Option<std::net::SocketAddrV6> {
+    addresses.iter().find_map(|addr| match addr {
+        SocketAddr::V6(addr) => Some(*addr),
+        SocketAddr::V4(_) => None,
+    })
+}
+
 impl Ord for ServerDescriptor {
     fn cmp(&self, other: &Self) -> Ordering {
         self.timestamp
@@ -199,3 +347,197 @@ impl PartialOrd for ServerDescriptor {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::descriptor::VersionnedType;
+
+    const BASE: &str = include_str!("../../../../tests/server_descriptor_test");
+
+    /// Parse the base descriptor, with `lines` inserted after its `published` line.
+    fn parse_with(lines: &str) -> ServerDescriptor {
+        let raw = BASE.replacen(
+            "published 2022-07-26 20:01:03\n",
+            &format!("published 2022-07-26 20:01:03\n{lines}"),
+            1,
+        );
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        ServerDescriptor::parse(body, vt.version).unwrap()
+    }
+
+    /// Remove the line starting with `keyword`, and the object following it if any.
+    fn remove_line(desc: &str, keyword: &str) -> String {
+        let mut lines = desc.lines().peekable();
+        let mut kept = Vec::new();
+        while let Some(line) = lines.next() {
+            if line.split(' ').next() != Some(keyword) {
+                kept.push(line);
+                continue;
+            }
+            if lines
+                .peek()
+                .is_some_and(|next| next.starts_with("-----BEGIN"))
+            {
+                for object_line in lines.by_ref() {
+                    if object_line.starts_with("-----END") {
+                        break;
+                    }
+                }
+            }
+        }
+        kept.join("\n") + "\n"
+    }
+
+    #[test]
+    fn test_without_ed25519() {
+        let mut raw = BASE.to_owned();
+        for keyword in [
+            "identity-ed25519",
+            "master-key-ed25519",
+            "onion-key-crosscert",
+            "ntor-onion-key-crosscert",
+            "router-sig-ed25519",
+        ] {
+            raw = remove_line(&raw, keyword);
+        }
+        assert!(!raw.contains("CERT"));
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        let desc = ServerDescriptor::parse(body, vt.version).unwrap();
+        assert_eq!(desc.identity_ed25519, None);
+        assert_eq!(desc.master_key_ed25519, None);
+        assert_eq!(desc.onion_key_crosscert, None);
+        assert_eq!(desc.ntor_onion_key_crosscert, None);
+        assert_eq!(desc.router_sig_ed25519, None);
+        assert!(desc
+            .router_signature
+            .starts_with("-----BEGIN SIGNATURE-----"));
+
+        let full = parse_with("");
+        assert!(full.identity_ed25519.is_some());
+        assert_eq!(
+            full.master_key_ed25519.as_deref(),
+            Some("z3fDicBkd51N9S/tTqVb1a/kcWeLIVSrFbMuUgk+5O0")
+        );
+        assert_eq!(full.ntor_onion_key_crosscert.unwrap().1, 0);
+
+        let raw = remove_line(BASE, "router-signature");
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        assert!(ServerDescriptor::parse(body, vt.version).is_err());
+    }
+
+    #[test]
+    fn test_ipv4_only() {
+        let desc = parse_with("");
+        assert_eq!(desc.ipv4, Ipv4Addr::new(130, 61, 145, 170));
+        assert_eq!(desc.ipv6, None);
+        assert_eq!(desc.or_port_v6, None);
+        assert!(desc.or_addresses.is_empty());
+        assert_eq!(desc.overload, None);
+        assert_eq!(desc.overload_ratelimits, None);
+        assert_eq!(desc.overload_fd_exhausted, None);
+        assert!(!desc.cache_extra_info);
+        assert!(desc.family.is_empty());
+        assert!(!desc.allow_single_hop_exits);
+    }
+
+    #[test]
+    fn test_family_and_flags() {
+        let desc = parse_with(
+            "caches-extra-info\nfamily $0011223344556677889900112233445566778899 $AABBCCDDEEFF00112233445566778899AABBCCDD\nallow-single-hop-exits\n",
+        );
+        assert!(desc.cache_extra_info);
+        assert!(desc.allow_single_hop_exits);
+        assert_eq!(
+            desc.family,
+            [
+                "$0011223344556677889900112233445566778899",
+                "$AABBCCDDEEFF00112233445566778899AABBCCDD"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identity() {
+        let desc = parse_with("");
+        assert_eq!(
+            desc.identity_hex(),
+            "1dd73bf4237f8c97b37459a64080ff7af37b6757"
+        );
+        assert_eq!(desc.identity_bytes().unwrap()[19], 0x57);
+    }
+
+    #[test]
+    fn test_ipv6_loopback() {
+        let desc = parse_with("or-address [::1]:9001\n");
+        assert_eq!(desc.ipv6, Some(Ipv6Addr::LOCALHOST));
+        assert_eq!(desc.or_port_v6, Some(9001));
+        assert_eq!(desc.or_addresses, ["[::1]:9001".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_dual_stack() {
+        let desc = parse_with("or-address [2603:c020:8010:977e::1]:9001\n");
+        assert_eq!(desc.ipv4, Ipv4Addr::new(130, 61, 145, 170));
+        assert_eq!(desc.or_port, 9001);
+        assert_eq!(desc.ipv6, Some("2603:c020:8010:977e::1".parse().unwrap()));
+        assert_eq!(desc.or_port_v6, Some(9001));
+    }
+
+    #[test]
+    fn test_multiple_or_addresses() {
+        let desc = parse_with(
+            "or-address 130.61.145.171:443\nor-address [2603:c020::1]:9001\nor-address [2603:c020::2]:443\n",
+        );
+        assert_eq!(desc.or_addresses.len(), 3);
+        assert_eq!(desc.or_addresses[0], "130.61.145.171:443".parse().unwrap());
+        // ipv6 and or_port_v6 come from the first IPv6 address
+        assert_eq!(desc.ipv6, Some("2603:c020::1".parse().unwrap()));
+        assert_eq!(desc.or_port_v6, Some(9001));
+    }
+
+    #[test]
+    fn test_overload_general() {
+        let desc = parse_with("overload-general 1 2022-07-26 19:00:00\n");
+        assert_eq!(
+            desc.overload,
+            Some((1, Utc.ymd(2022, 7, 26).and_hms(19, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_overload_ratelimits() {
+        let desc =
+            parse_with("overload-ratelimits 1 2022-07-26 19:00:00 1073741824 2147483648 23 42\n");
+        assert_eq!(
+            desc.overload_ratelimits,
+            Some((
+                1,
+                Utc.ymd(2022, 7, 26).and_hms(19, 0, 0),
+                1073741824,
+                2147483648,
+                23,
+                42
+            ))
+        );
+        // missing counts
+        let raw = BASE.replacen(
+            "published 2022-07-26 20:01:03\n",
+            "published 2022-07-26 20:01:03\noverload-ratelimits 1 2022-07-26 19:00:00 1073741824\n",
+            1,
+        );
+        let (body, vt) = VersionnedType::parse(&raw).unwrap();
+        assert!(ServerDescriptor::parse(body, vt.version).is_err());
+    }
+
+    #[test]
+    fn test_overload_fd_exhausted() {
+        let desc = parse_with("overload-fd-exhausted 1 2022-07-26 20:00:00\n");
+        assert_eq!(
+            desc.overload_fd_exhausted,
+            Some((1, Utc.ymd(2022, 7, 26).and_hms(20, 0, 0)))
+        );
+    }
+}
@@ -19,6 +19,29 @@ pub(crate) fn descriptor_lines(input: &str) -> Result<HashMap<&str, Vec<Descript
     Ok(desc.0)
 }
 
+/// Format a keyword for error messages, showing whether it was used with the `opt` prefix.
+pub(crate) fn keyword_label(keyword: &str, lines: &[DescriptorLine]) -> String {
+    if lines.iter().any(|line| line.is_optional) {
+        format!("`opt {keyword}`")
+    } else {
+        format!("`{keyword}`")
+    }
+}
+
+/// Build the error returned when a line expected at most once appeared multiple times,
+/// listing where each occurrence was found.
+pub(crate) fn duplicate_line_error(keyword: &str, lines: &[DescriptorLine]) -> ErrorKind {
+    let line_numbers = lines
+        .iter()
+        .map(|line| line.line.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    ErrorKind::MalformedDesc(format!(
+        "line {} appeared multiple times (lines {line_numbers})",
+        keyword_label(keyword, lines)
+    ))
+}
+
 macro_rules! extract_desc {
     ( $map:expr =>
         $struct:ident $rest:ident {
@@ -60,12 +83,10 @@ macro_rules! extract_desc {
     }};
     (@extractor uniq $rest:ident ($map:expr), ($keyword:expr) [$($name:ident),*] [$($opt:ident),*]) => {
         let mut __item = $map.remove($keyword).ok_or(ErrorKind::MalformedDesc(
-                       concat!("line ", $keyword, " missing").to_owned()
+                       concat!("required line `", $keyword, "` missing").to_owned()
                 ))?;
         if __item.len() != 1 {
-            return Err(ErrorKind::MalformedDesc(
-                       concat!("line ", $keyword, " appeared multiple times").to_owned()
-                    ).into());
+            return Err(duplicate_line_error($keyword, &__item).into());
         }
         let __item = __item.pop().unwrap();
 
@@ -75,12 +96,10 @@ macro_rules! extract_desc {
     };
     (@extractor cert $rest:ident ($map:expr), ($keyword:expr) [$cert:ident $(, $name:ident),*] [$($opt:ident),*]) => {
         let mut __item = $map.remove($keyword).ok_or(ErrorKind::MalformedDesc(
-                       concat!("line ", $keyword, " missing").to_owned()
+                       concat!("required line `", $keyword, "` missing").to_owned()
                 ))?;
         if __item.len() != 1 {
-            return Err(ErrorKind::MalformedDesc(
-                       concat!("line ", $keyword, " appeared multiple times").to_owned()
-                    ).into());
+            return Err(duplicate_line_error($keyword, &__item).into());
         }
         let __item = __item.pop().unwrap();
         let $cert = __item.cert.ok_or(ErrorKind::MalformedDesc(
@@ -91,14 +110,26 @@ macro_rules! extract_desc {
             @pattern (&__item.values[..]) $rest [$($name)*] [$($opt)*] ($keyword)
         }
     };
+    (@extractor opt $rest:ident ($map:expr), ($keyword:expr) [] []) => {
+        let __item = $map.remove($keyword);
+        let mut __item2 = None;
+        let $rest = match __item {
+            Some(__item) if __item.len() != 1 => {
+                return Err(duplicate_line_error($keyword, &__item).into());
+            },
+            Some(mut __item) => {
+                __item2 = __item.pop();
+                Some(&__item2.as_ref().unwrap().values[..])
+            },
+            None => None,
+        };
+    };
     (@extractor opt $rest:ident ($map:expr), ($keyword:expr) [$($name:ident),*] []) => {
         let __item = $map.remove($keyword);
         let mut __item2 = None;
         let ($rest, $($name),*) = match __item {
             Some(__item) if __item.len() != 1 => {
-                return Err(ErrorKind::MalformedDesc(
-                       concat!("line ", $keyword, " appeared multiple times").to_owned()
-                   ).into());
+                return Err(duplicate_line_error($keyword, &__item).into());
             },
             Some(mut __item) => {
                 __item2 = __item.pop();
@@ -117,6 +148,52 @@ macro_rules! extract_desc {
             None => std::default::Default::default(),
         };
     };
+    (@extractor opt_cert $rest:ident ($map:expr), ($keyword:expr) [$cert:ident] []) => {
+        let __item = $map.remove($keyword);
+        let mut __item2 = None;
+        let ($cert, $rest) = match __item {
+            Some(__item) if __item.len() != 1 => {
+                return Err(duplicate_line_error($keyword, &__item).into());
+            },
+            Some(mut __item) => {
+                __item2 = __item.pop();
+                let __item2 = __item2.as_ref().unwrap();
+                let cert = __item2.cert.ok_or(ErrorKind::MalformedDesc(
+                               concat!("line ", $keyword, " miss a certificate").to_owned()
+                        ))?;
+                (Some(cert), Some(&__item2.values[..]))
+            },
+            None => (None, None),
+        };
+    };
+    (@extractor opt_cert $rest:ident ($map:expr), ($keyword:expr) [$cert:ident $(, $name:ident)*] []) => {
+        let __item = $map.remove($keyword);
+        let mut __item2 = None;
+        let ($cert, $rest, $($name),*) = match __item {
+            Some(__item) if __item.len() != 1 => {
+                return Err(duplicate_line_error($keyword, &__item).into());
+            },
+            Some(mut __item) => {
+                __item2 = __item.pop();
+                let __item2 = __item2.as_ref().unwrap();
+                let cert = __item2.cert.ok_or(ErrorKind::MalformedDesc(
+                               concat!("line ", $keyword, " miss a certificate").to_owned()
+                        ))?;
+                #[allow(unreachable_patterns)]
+                match &__item2.values[..] {
+                    [$($name,)* rest @ ..] => {
+                        (Some(cert), Some(rest), $(Some(*$name),)*)
+                    },
+                    _ => {
+                        return Err(ErrorKind::MalformedDesc(
+                                concat!("missing parameters to ", $keyword).to_owned()
+                            ).into());
+                    },
+                }
+            },
+            None => std::default::Default::default(),
+        };
+    };
     (@extractor multi $rest:ident ($map:expr), ($($keyword:expr),*) [] []) => {
         let mut $rest = vec![];
         $(
@@ -167,4 +244,96 @@ pub(crate) fn hashmap_from_kv_vec(data: Vec<&str>) -> Result<HashMap<String, Str
         .collect()
 }
 
+/// Parse the values of a `bandwidth` line, which must be exactly 3 integers.
+pub(crate) fn parse_bandwidth(values: &[&str]) -> Result<(u64, u64, u64), Error> {
+    use crate::descriptor::nom_combinators::{all_consuming, bandwidth_triple};
+
+    let line = values.join(" ");
+    let (_, bandwidth) = all_consuming(bandwidth_triple)(&line)?;
+    Ok(bandwidth)
+}
+
+/// Split multiple concatenated documents, each starting with a `header_keyword` line.
+///
+/// Whatever precedes the first such line belongs to the first document.
+pub(crate) fn split_on_header<'a>(input: &'a str, header_keyword: &str) -> Vec<&'a str> {
+    split_on_optional_header(input, None, header_keyword)
+}
+
+/// Split multiple concatenated documents, each starting with a `header_keyword` line, or with
+/// a `leading_keyword` line when one comes right before it.
+///
+/// Whatever precedes the first document start belongs to the first document.
+pub(crate) fn split_on_optional_header<'a>(
+    input: &'a str,
+    leading_keyword: Option<&str>,
+    header_keyword: &str,
+) -> Vec<&'a str> {
+    let starts_with_keyword = |line: &str, keyword: &str| {
+        line.strip_prefix(keyword)
+            .map(|rest| rest.starts_with([' ', '\r', '\n']) || rest.is_empty())
+            .unwrap_or(false)
+    };
+
+    let mut starts: Vec<usize> = Vec::new();
+    let mut offset = 0;
+    let mut after_leading = false;
+    for line in input.split_inclusive('\n') {
+        if leading_keyword.is_some_and(|keyword| starts_with_keyword(line, keyword)) {
+            starts.push(offset);
+            after_leading = true;
+        } else {
+            if starts_with_keyword(line, header_keyword) && !after_leading {
+                starts.push(offset);
+            }
+            after_leading = false;
+        }
+        offset += line.len();
+    }
+    match starts.first_mut() {
+        Some(first) => *first = 0,
+        None => starts.push(0),
+    }
+
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain(std::iter::once(&input.len())))
+        .map(|(start, end)| &input[*start..*end])
+        .collect()
+}
+
 pub(crate) use extract_desc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_on_header() {
+        assert_eq!(split_on_header("", "published"), vec![""]);
+        assert_eq!(
+            split_on_header("published 1\na\npublished 2\nb\n", "published"),
+            vec!["published 1\na\n", "published 2\nb\n"]
+        );
+        assert_eq!(
+            split_on_header("x\npublished 1\npublished-not 2\npublished\n", "published"),
+            vec!["x\npublished 1\npublished-not 2\n", "published\n"]
+        );
+    }
+
+    #[test]
+    fn test_split_on_optional_header() {
+        assert_eq!(
+            split_on_optional_header(
+                "version 3\npublished 1\na\npublished 2\nb\nversion 3\npublished 3\nc\n",
+                Some("version"),
+                "published"
+            ),
+            vec![
+                "version 3\npublished 1\na\n",
+                "published 2\nb\n",
+                "version 3\npublished 3\nc\n"
+            ]
+        );
+    }
+}
@@ -1,6 +1,8 @@
 pub mod file_reader;
+pub mod fingerprint;
 pub mod kind;
 
+pub use fingerprint::Fingerprint;
 pub use kind::{Descriptor, Type, VersionnedType};
 
 pub(crate) mod nom_combinators {
@@ -8,11 +10,15 @@ pub(crate) mod nom_combinators {
 
     use chrono::{DateTime, TimeZone, Utc};
 
+    pub use nom::branch::alt;
     pub use nom::bytes::complete::{tag, take, take_till, take_until};
     pub use nom::character::complete::{
         anychar, char, hex_digit1, line_ending, space0, space1, u32,
     };
-    pub use nom::combinator::{eof, iterator, map, map_parser, map_res, opt, peek};
+    pub use nom::combinator::{
+        all_consuming, eof, iterator, map, map_parser, map_res, opt, peek, verify,
+    };
+    pub use nom::error::context;
     pub use nom::multi::fold_many_m_n;
     pub use nom::sequence::tuple;
     pub use nom::Parser;
@@ -27,11 +33,47 @@ pub(crate) mod nom_combinators {
         take_till(|c| c == ' ' || c == '\n')(input)
     }
 
+    /// Parse a whole line, returning its content without the line ending. Accept both `\n` and
+    /// `\r\n`.
+    pub fn take_line(input: &str) -> nom::IResult<&str, &str, nom::error::Error<&str>> {
+        let (i, line) = take_till(|c| c == '\r' || c == '\n')(input)?;
+        let (i, _) = line_ending(i)?;
+        Ok((i, line))
+    }
+
+    /// Parse an unsigned integer, and reject it if `validator` returns false. `label` is added as
+    /// context to errors, which is why they are [`VerboseError`](nom::error::VerboseError).
+    pub fn validated_u64<'a, F: Fn(u64) -> bool>(
+        validator: F,
+        label: &'static str,
+    ) -> impl FnMut(&'a str) -> nom::IResult<&'a str, u64, nom::error::VerboseError<&'a str>> {
+        context(
+            label,
+            verify(nom::character::complete::u64, move |n: &u64| validator(*n)),
+        )
+    }
+
+    /// Parse a TCP port number.
+    pub fn port_number(input: &str) -> nom::IResult<&str, u16, nom::error::VerboseError<&str>> {
+        map(validated_u64(|n| n <= 65535, "port"), |n| n as u16)(input)
+    }
+
     /// Parse a 160 bit hexadecimal bloc, which correspond to Tor relay fingerprint.
     pub fn fingerprint(input: &str) -> nom::IResult<&str, &str, nom::error::Error<&str>> {
         map_parser(hex_digit1, take(40usize))(input)
     }
 
+    /// Parse the values of a `bandwidth` line: average, burst and observed bandwidth.
+    pub fn bandwidth_triple(
+        input: &str,
+    ) -> nom::IResult<&str, (u64, u64, u64), nom::error::Error<&str>> {
+        use nom::character::complete::u64;
+        map(
+            tuple((u64, space1, u64, space1, u64)),
+            |(avg, _, burst, _, observed)| (avg, burst, observed),
+        )(input)
+    }
+
     /// Parse a date
     pub fn date(input: &str) -> nom::IResult<&str, DateTime<Utc>, nom::error::Error<&str>> {
         let format = "%Y-%m-%d %H:%M:%S";
@@ -40,7 +82,18 @@ pub(crate) mod nom_combinators {
         })(input)
     }
 
-    /// Parse a set of key=value separated by spaces, until end of line
+    /// Parse a date, with or without seconds, such as `yyyy-mm-dd hh:mm` in CollecTor indexes.
+    /// Missing seconds are set to 0.
+    pub fn date_flexible(
+        input: &str,
+    ) -> nom::IResult<&str, DateTime<Utc>, nom::error::Error<&str>> {
+        let without_seconds = map_res(take("yyyy-mm-dd hh:mm".len()), |s| {
+            Utc.datetime_from_str(s, "%Y-%m-%d %H:%M")
+        });
+        alt((date, without_seconds))(input)
+    }
+
+    /// Parse a set of key=value separated by spaces, until end of line or end of input
     pub fn kv_space(
         input: &str,
     ) -> nom::IResult<&str, HashMap<String, String>, nom::error::Error<&str>> {
@@ -51,14 +104,14 @@ pub(crate) mod nom_combinators {
                 take_till(|c| c == '='),
                 char('='),
                 word,
-                peek(anychar),
+                peek(opt(anychar)),
             )),
         );
 
         let mut kv = HashMap::new();
         for (_, k, _, v, eol) in &mut it {
             kv.insert(k.to_owned(), v.to_owned());
-            if eol == '\n' {
+            if eol.is_none_or(|c| c == '\n') {
                 break;
             }
         }
@@ -104,4 +157,115 @@ pub(crate) mod nom_combinators {
 
         Ok((i, &input[..len]))
     }
+
+    /// Parse a PEM content if `input` starts with one. Unlike `opt(cert)`, input starting with
+    /// `-----BEGIN ` must be a valid PEM content, and other input is returned untouched without
+    /// attempting to parse it.
+    pub fn try_cert(input: &str) -> nom::IResult<&str, Option<&str>, nom::error::Error<&str>> {
+        if input.starts_with("-----BEGIN ") {
+            map(cert, Some)(input)
+        } else {
+            Ok((input, None))
+        }
+    }
+
+    /// Parse a PEM content like [`cert`], but reject it if its content isn't made only of base64
+    /// characters.
+    pub fn cert_strict(input: &str) -> nom::IResult<&str, &str, nom::error::Error<&str>> {
+        let start_len = input.len();
+
+        let (i, _) = tag("-----BEGIN ")(input)?;
+        let (i, _label) = take_until("--")(i)?;
+        let (i, _) = tag("-----\n")(i)?;
+        let (i, _b64) = verify(take_until("--"), |b64: &str| {
+            b64.chars()
+                .all(|c| c == '\n' || c.is_ascii_alphanumeric() || "+/=".contains(c))
+        })(i)?;
+        let (i, _) = tag("-----END ")(i)?;
+        let (i, _label) = take_until("--")(i)?;
+        let (i, _) = tag("-----\n")(i)?;
+
+        let len = start_len - i.len();
+
+        Ok((i, &input[..len]))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_take_line() {
+            assert_eq!(take_line("abc def\nghi"), Ok(("ghi", "abc def")));
+            assert_eq!(take_line("abc def\r\nghi"), Ok(("ghi", "abc def")));
+            assert_eq!(take_line("\n"), Ok(("", "")));
+            assert!(take_line("no line ending").is_err());
+        }
+
+        #[test]
+        fn test_kv_space() {
+            let (i, kv) = kv_space(" a=1 b=2\nnext").unwrap();
+            assert_eq!(i, "\nnext");
+            assert_eq!(kv.len(), 2);
+            let (i, kv) = kv_space(" a=1 b=2").unwrap();
+            assert_eq!(i, "");
+            assert_eq!(kv.get("b").map(String::as_str), Some("2"));
+        }
+
+        #[test]
+        fn test_port_number() {
+            assert_eq!(port_number("9001"), Ok(("", 9001)));
+            assert_eq!(port_number("65535 "), Ok((" ", 65535)));
+            assert!(port_number("65536").is_err());
+            assert!(port_number("-1").is_err());
+
+            let err = crate::error::Error::from(port_number("65536").unwrap_err());
+            assert!(err.to_string().contains("Context(\"port\")"), "{err}");
+        }
+
+        #[test]
+        fn test_try_cert() {
+            let pem = "-----BEGIN SIGNATURE-----\nAAEC+/8=\n-----END SIGNATURE-----\n";
+            assert_eq!(
+                try_cert(&format!("{pem}next line\n")),
+                Ok(("next line\n", Some(pem)))
+            );
+            assert_eq!(try_cert("next line\n"), Ok(("next line\n", None)));
+            assert!(try_cert("-----BEGIN SIGNATURE-----\nAAEC+/8=\n").is_err());
+        }
+
+        #[test]
+        fn test_bandwidth_triple() {
+            assert_eq!(
+                bandwidth_triple("1073741824 1073741824 65536"),
+                Ok(("", (1073741824, 1073741824, 65536)))
+            );
+            assert!(bandwidth_triple("1 2").is_err());
+            assert!(bandwidth_triple("1 -2 3").is_err());
+            assert!(all_consuming(bandwidth_triple)("1 2 3 4").is_err());
+        }
+
+        #[test]
+        fn test_date_flexible() {
+            let with_seconds = Utc.ymd(2022, 7, 18).and_hms(0, 1, 2);
+            let without_seconds = Utc.ymd(2022, 7, 18).and_hms(0, 1, 0);
+            assert_eq!(date_flexible("2022-07-18 00:01:02"), Ok(("", with_seconds)));
+            assert_eq!(date_flexible("2022-07-18 00:01"), Ok(("", without_seconds)));
+            assert_eq!(
+                date_flexible("2022-07-18 00:01 (86400 s)"),
+                Ok((" (86400 s)", without_seconds))
+            );
+            assert!(date_flexible("2022-07-18").is_err());
+        }
+
+        #[test]
+        fn test_cert_strict() {
+            let valid = "-----BEGIN SIGNATURE-----\nAAEC+/8=\n-----END SIGNATURE-----\n";
+            assert_eq!(cert_strict(valid), Ok(("", valid)));
+
+            let invalid = "-----BEGIN SIGNATURE-----\nAA#C+/8=\n-----END SIGNATURE-----\n";
+            assert!(cert(invalid).is_ok());
+            assert!(cert_strict(invalid).is_err());
+        }
+    }
 }
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::error::Error;
+
+/// What is known about a file which was verified to have the hash listed in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadState {
+    /// Path of the file, relative to the index
+    pub path: String,
+    pub sha256: [u8; 32],
+    pub verified_at: DateTime<Utc>,
+}
+
+/// Record of files already verified, persisted as json so restarting doesn't require hashing
+/// every file again.
+///
+/// A file is trusted without being hashed when its record has the expected hash, is less than
+/// 24 hours old, and the file wasn't modified since. This is only a cache: failing to save it
+/// means files get hashed again next time.
+#[derive(Debug)]
+pub struct DownloadStateCache {
+    path: PathBuf,
+    states: Mutex<HashMap<String, DownloadState>>,
+}
+
+impl DownloadStateCache {
+    /// How long a verification is trusted.
+    pub fn max_age() -> Duration {
+        Duration::hours(24)
+    }
+
+    /// Load the cache stored in `path`. A missing file gives an empty cache.
+    pub async fn load<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let states: Vec<DownloadState> = match fs::read(&path).await {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(DownloadStateCache {
+            path,
+            states: Mutex::new(
+                states
+                    .into_iter()
+                    .map(|state| (state.path.clone(), state))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Write the cache back to the file it was loaded from.
+    pub async fn save(&self) -> Result<(), Error> {
+        let json = {
+            let states = self.states.lock().unwrap();
+            let mut states: Vec<_> = states.values().collect();
+            states.sort_by(|a, b| a.path.cmp(&b.path));
+            serde_json::to_vec(&states)?
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&self.path, json).await?;
+        Ok(())
+    }
+
+    /// Whether `file`, stored at `data_path`, can be assumed to have hash `sha256` without
+    /// reading it.
+    pub async fn is_verified(&self, file: &str, data_path: &Path, sha256: &[u8; 32]) -> bool {
+        let verified_at = match self.states.lock().unwrap().get(file) {
+            Some(state) if &state.sha256 == sha256 => state.verified_at,
+            _ => return false,
+        };
+        if Utc::now() - verified_at >= Self::max_age() {
+            return false;
+        }
+        let Ok(modified) = fs::metadata(data_path)
+            .await
+            .and_then(|meta| meta.modified())
+        else {
+            return false;
+        };
+        DateTime::<Utc>::from(modified) <= verified_at
+    }
+
+    /// Record that `file` was just verified to have hash `sha256`.
+    pub fn record(&self, file: &str, sha256: &[u8; 32]) {
+        self.states.lock().unwrap().insert(
+            file.to_owned(),
+            DownloadState {
+                path: file.to_owned(),
+                sha256: *sha256,
+                verified_at: Utc::now(),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_download_state_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("state.json");
+        let data_path = dir.path().join("file");
+        fs::write(&data_path, "content").await.unwrap();
+
+        let cache = DownloadStateCache::load(&cache_path).await.unwrap();
+        assert!(cache.is_empty());
+        assert!(!cache.is_verified("file", &data_path, &[1; 32]).await);
+
+        cache.record("file", &[1; 32]);
+        assert!(cache.is_verified("file", &data_path, &[1; 32]).await);
+        assert!(!cache.is_verified("file", &data_path, &[2; 32]).await);
+        assert!(!cache.is_verified("other", &data_path, &[1; 32]).await);
+        cache.save().await.unwrap();
+
+        let reloaded = DownloadStateCache::load(&cache_path).await.unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert!(reloaded.is_verified("file", &data_path, &[1; 32]).await);
+
+        // a verification older than the file doesn't count
+        {
+            let mut states = reloaded.states.lock().unwrap();
+            let state = states.get_mut("file").unwrap();
+            state.verified_at = state.verified_at - Duration::hours(1);
+        }
+        assert!(!reloaded.is_verified("file", &data_path, &[1; 32]).await);
+        fs::remove_file(&data_path).await.unwrap();
+        reloaded.record("file", &[1; 32]);
+        assert!(!reloaded.is_verified("file", &data_path, &[1; 32]).await);
+    }
+}
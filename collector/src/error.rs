@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::{io, net, num, str::ParseBoolError};
 
 use thiserror::Error;
@@ -13,6 +14,8 @@ pub enum Error {
     Reqwest(#[from] reqwest::Error),
     #[error("Serialization error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("Configuration error: {0}")]
+    Config(#[from] toml::de::Error),
     #[error("collector error: {0}")]
     Collector(#[from] ErrorKind),
     #[error("NetworkStatus error: {0}")]
@@ -23,6 +26,31 @@ pub enum Error {
     ParseIpV6(#[from] net::AddrParseError),
     #[error("ParseBool error: {0}")]
     ParseBool(#[from] ParseBoolError),
+    #[error("in {}{}: {source}", .file.display(), entry_context(.entry))]
+    InFile {
+        source: Box<Error>,
+        file: PathBuf,
+        /// Path of the entry inside `file`, if it is an archive
+        entry: Option<PathBuf>,
+    },
+}
+
+impl Error {
+    /// Add the file, and for archives the entry, which caused this error.
+    pub(crate) fn in_file<F: Into<PathBuf>>(self, file: F, entry: Option<PathBuf>) -> Self {
+        Error::InFile {
+            source: Box::new(self),
+            file: file.into(),
+            entry,
+        }
+    }
+}
+
+fn entry_context(entry: &Option<PathBuf>) -> String {
+    entry
+        .as_ref()
+        .map(|entry| format!(" ({})", entry.display()))
+        .unwrap_or_default()
 }
 
 #[derive(Debug, Clone)]
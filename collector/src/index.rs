@@ -1,12 +1,16 @@
-use std::collections::BTreeSet;
-use std::ops::RangeBounds;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::{RangeBounds, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::fs;
 use tokio::io::AsyncReadExt;
 
+use crate::descriptor::file_reader::{CompressionType, FileReader};
 use crate::descriptor::{Type, VersionnedType};
 use crate::error::Error;
 
@@ -35,7 +39,12 @@ impl Index {
         let mut file = fs::File::open(path).await?;
         let mut json = Vec::new();
         file.read_to_end(&mut json).await?;
-        let index: SerializedIndex = serde_json::from_slice(&json)?;
+        Self::from_slice(&json)
+    }
+
+    /// Parse an index from its json representation.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        let index: SerializedIndex = serde_json::from_slice(bytes)?;
 
         let files = index
             .list_files()
@@ -50,6 +59,177 @@ impl Index {
             files,
         })
     }
+
+    /// Build an index from the descriptor files found under `path`, such as a directory
+    /// synchronized from a CollecTor instance without its index. Files not starting with an
+    /// `@type` line, hidden files, `.tmp` files and `index.json` are ignored.
+    ///
+    /// Publication dates aren't known without decoding every descriptor, so they are
+    /// approximated: monthly archives named like `...-YYYY-MM.tar.xz` cover their whole month,
+    /// other files are assumed to be published when they were last modified.
+    pub async fn from_directory<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_directory_excluding(path, &[]).await
+    }
+
+    /// Same as [`Index::from_directory`], but directories in `excluded` and their content are
+    /// ignored.
+    pub async fn from_directory_excluding<P: AsRef<Path>>(
+        path: P,
+        excluded: &[PathBuf],
+    ) -> Result<Self, Error> {
+        let root = fs::canonicalize(path.as_ref()).await?;
+        let mut excluded_dirs = Vec::new();
+        for dir in excluded {
+            // a directory which doesn't exist can't be walked into anyway
+            if let Ok(dir) = fs::canonicalize(dir).await {
+                excluded_dirs.push(dir);
+            }
+        }
+
+        let mut files = BTreeSet::new();
+        let mut directories = vec![root.clone()];
+        while let Some(directory) = directories.pop() {
+            let mut entries = fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                // .tmp files are downloads which didn't complete
+                if name.starts_with('.') || name.ends_with(".tmp") || name == "index.json" {
+                    continue;
+                }
+                let file_type = entry.file_type().await?;
+                if file_type.is_dir() {
+                    if excluded_dirs.contains(&entry.path()) {
+                        continue;
+                    }
+                    directories.push(entry.path());
+                } else if file_type.is_file() {
+                    if let Some(file) = File::from_local(&root, &entry.path()).await? {
+                        files.insert(file);
+                    }
+                }
+            }
+        }
+        Ok(Index {
+            creation_time: Utc::now(),
+            path: String::new(),
+            files,
+        })
+    }
+
+    /// Serialize this index to json, in the same format CollecTor uses.
+    pub fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let mut index = SerializedIndex {
+            index_created: self.creation_time,
+            build_revision: String::new(),
+            path: self.path.clone(),
+            directories: Vec::new(),
+            files: Vec::new(),
+        };
+        for file in &self.files {
+            insert_file(&mut index.directories, &mut index.files, &file.path, file);
+        }
+        Ok(serde_json::to_vec(&index)?)
+    }
+
+    /// Compare base url and files of two indexes, ignoring when they were created.
+    pub fn files_equal(&self, other: &Index) -> bool {
+        self.path == other.path && self.same_files_as(other)
+    }
+
+    /// Compare only the files of two indexes.
+    pub fn same_files_as(&self, other: &Index) -> bool {
+        self.files == other.files
+    }
+
+    /// Combine two indexes, keeping files of both. When both contain a file with the same path,
+    /// the entry with the newest `last_modified` is kept, `a` winning ties.
+    ///
+    /// The resulting index uses the base url of `a`, and the newest creation time of the two.
+    pub fn merge(a: Index, b: Index) -> Index {
+        let mut files: HashMap<String, File> = HashMap::new();
+        for file in a.files.into_iter().chain(b.files) {
+            match files.get(&file.path) {
+                Some(existing) if existing.last_modified >= file.last_modified => (),
+                _ => {
+                    files.insert(file.path.clone(), file);
+                }
+            }
+        }
+
+        Index {
+            creation_time: a.creation_time.max(b.creation_time),
+            path: a.path,
+            files: files.into_values().collect(),
+        }
+    }
+
+    /// Get files containing descriptors of type `ttype` published during `range`.
+    pub fn files_in_range<'a: 'b, 'b, R: RangeBounds<DateTime<Utc>>>(
+        &'a self,
+        ttype: &'b Type,
+        range: &'b R,
+    ) -> impl Iterator<Item = &'a File> + 'b {
+        self.files
+            .iter()
+            .filter(move |file| file.type_matches(ttype) && file.overlap(range))
+    }
+
+    /// Count files containing descriptors of type `ttype` published during `range`.
+    pub fn file_count_in_range<R: RangeBounds<DateTime<Utc>>>(
+        &self,
+        ttype: &Type,
+        range: &R,
+    ) -> usize {
+        self.files_in_range(ttype, range).count()
+    }
+
+    /// Get the file of type `ttype` with the latest `last_published`.
+    pub fn newest_file<'a>(&'a self, ttype: &Type) -> Option<&'a File> {
+        self.files
+            .iter()
+            .filter(|file| file.type_matches(ttype))
+            .max_by_key(|file| file.last_published)
+    }
+
+    /// Get the file of type `ttype` with the earliest `first_published`.
+    pub fn oldest_file<'a>(&'a self, ttype: &Type) -> Option<&'a File> {
+        self.files
+            .iter()
+            .filter(|file| file.type_matches(ttype))
+            .min_by_key(|file| file.first_published)
+    }
+
+    /// Get the range of dates for which descriptors of type `ttype` are available.
+    pub fn date_range(&self, ttype: &Type) -> Option<RangeInclusive<DateTime<Utc>>> {
+        let oldest = self.oldest_file(ttype)?;
+        let newest = self.newest_file(ttype)?;
+        Some(oldest.first_published..=newest.last_published)
+    }
+
+    /// Get files of type `ttype` grouped by the `(year, month)` of their `first_published`.
+    pub fn files_by_month<'a>(&'a self, ttype: &Type) -> BTreeMap<(i32, u32), Vec<&'a File>> {
+        let mut months: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for file in self.files.iter().filter(|file| file.type_matches(ttype)) {
+            let month = (file.first_published.year(), file.first_published.month());
+            months.entry(month).or_default().push(file);
+        }
+        months
+    }
+
+    /// Get the `(year, month)` for which at least one file of type `ttype` is available, in
+    /// chronological order.
+    pub fn months_covered(&self, ttype: &Type) -> Vec<(i32, u32)> {
+        self.files_by_month(ttype).into_keys().collect()
+    }
+}
+
+impl FromStr for Index {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_slice(s.as_bytes())
+    }
 }
 
 /// Collector index
@@ -80,6 +260,36 @@ impl SerializedIndex {
                 path.push(&file.path);
                 (path.join("/"), file)
             })
+            .chain(
+                self.files
+                    .iter()
+                    .map(|file| (file.path.clone(), file.clone())),
+            )
+    }
+}
+
+/// Add `file` to the tree of `directories`, creating those on the way to `path`.
+fn insert_file(directories: &mut Vec<Directory>, files: &mut Vec<File>, path: &str, file: &File) {
+    match path.split_once('/') {
+        Some((name, rest)) => {
+            let idx = match directories.iter().position(|dir| dir.path == name) {
+                Some(idx) => idx,
+                None => {
+                    directories.push(Directory {
+                        path: name.to_owned(),
+                        directories: Vec::new(),
+                        files: Vec::new(),
+                    });
+                    directories.len() - 1
+                }
+            };
+            let directory = &mut directories[idx];
+            insert_file(&mut directory.directories, &mut directory.files, rest, file);
+        }
+        None => files.push(File {
+            path: path.to_owned(),
+            ..file.clone()
+        }),
     }
 }
 
@@ -116,7 +326,9 @@ impl Directory {
 }
 
 /// Metadatas of a file
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+///
+/// Files are ordered chronologically: by `first_published`, then `last_published`, then `path`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct File {
     /// Path part leading to this file.
     pub path: String,
@@ -139,50 +351,145 @@ pub struct File {
     pub sha256: [u8; 32],
 }
 
+impl Ord for File {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.first_published
+            .cmp(&other.first_published)
+            .then_with(|| self.last_published.cmp(&other.last_published))
+            .then_with(|| self.path.cmp(&other.path))
+            // remaining fields only matter to stay consistent with Eq
+            .then_with(|| self.size.cmp(&other.size))
+            .then_with(|| self.last_modified.cmp(&other.last_modified))
+            .then_with(|| self.types.cmp(&other.types))
+            .then_with(|| self.sha256.cmp(&other.sha256))
+    }
+}
+
+impl PartialOrd for File {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl File {
     pub fn type_matches(&self, ttype: &Type) -> bool {
         self.types.iter().map(|vt| &vt.ttype).any(|tt| tt == ttype)
     }
 
     pub fn overlap<R: RangeBounds<DateTime<Utc>>>(&self, time_range: &R) -> bool {
-        if time_range.contains(&self.first_published)
-            || time_range.contains(&self.last_published)
-            || time_range.contains(&self.last_modified)
-        {
-            return true;
-        }
-        // only case left is when time_range is strictly included in first..=last
-        use std::ops::Bound::{Excluded, Included};
-        let get_bound = |bound| match bound {
-            Included(v) | Excluded(v) => Some(v),
-            _ => None,
-        };
+        use std::ops::Bound::{Excluded, Included, Unbounded};
 
-        let bound = get_bound(time_range.start_bound())
-            .or_else(|| get_bound(time_range.end_bound()))
-            .expect("if we are here, time_range can't be unbounded");
+        // an unbounded side of time_range can't exclude anything
+        let starts_before_range_end = match time_range.end_bound() {
+            Included(end) => self.first_published <= *end,
+            Excluded(end) => self.first_published < *end,
+            Unbounded => true,
+        };
+        let ends_after_range_start = match time_range.start_bound() {
+            Included(start) => self.last_published >= *start,
+            Excluded(start) => self.last_published > *start,
+            Unbounded => true,
+        };
 
-        self.time_range().contains(bound)
+        (starts_before_range_end && ends_after_range_start)
+            || time_range.contains(&self.last_modified)
     }
 
     pub fn time_range(&self) -> std::ops::RangeInclusive<DateTime<Utc>> {
         self.first_published..=self.last_published
     }
 
+    /// Whether this file is a tar archive. Compressed plain text files are not archives.
     pub fn is_archive(&self) -> bool {
-        self.path.ends_with(".tar") || self.path.contains(".tar.")
+        self.compression_type().is_tar()
+    }
+
+    /// How this file is packed, based on its extension.
+    pub fn compression_type(&self) -> CompressionType {
+        CompressionType::from_name(&self.path)
     }
 }
 
+impl File {
+    /// Describe the local file at `path`, with a path relative to `root`. Returns None if the
+    /// file doesn't contain descriptors.
+    async fn from_local(root: &Path, path: &Path) -> Result<Option<File>, Error> {
+        let types = match Box::pin(FileReader::read_file(path)).next().await {
+            Some(Ok(body)) => match VersionnedType::parse(&body) {
+                Ok((_, vt)) => vec![vt],
+                Err(_) => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        let metadata = fs::metadata(path).await?;
+        let last_modified: DateTime<Utc> = metadata.modified()?.into();
+        let relative_path = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let (first_published, last_published) =
+            archive_month(&relative_path).unwrap_or((last_modified, last_modified));
+
+        let mut file = fs::File::open(path).await?;
+        let mut buf = vec![0; 256 * 1024];
+        let mut hasher = Sha256::new();
+        loop {
+            let len = file.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+
+        Ok(Some(File {
+            path: relative_path,
+            size: metadata.len(),
+            last_modified,
+            types,
+            first_published,
+            last_published,
+            sha256: hasher.finalize().into(),
+        }))
+    }
+}
+
+/// Get the start and end of the month covered by an archive named like `...-YYYY-MM.tar.xz`.
+fn archive_month(path: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if !CompressionType::from_name(path).is_tar() {
+        return None;
+    }
+    let stem = path.rsplit('/').next()?.split(".tar").next()?;
+    let (rest, month) = stem.rsplit_once('-')?;
+    let (_, year) = rest.rsplit_once('-')?;
+    let start = Utc
+        .ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+        .single()?;
+    let next_month = if start.month() == 12 {
+        Utc.ymd(start.year() + 1, 1, 1)
+    } else {
+        Utc.ymd(start.year(), start.month() + 1, 1)
+    };
+    Some((
+        start.and_hms(0, 0, 0),
+        next_month.and_hms(0, 0, 0) - chrono::Duration::seconds(1),
+    ))
+}
+
 fn epoch() -> DateTime<Utc> {
     std::time::SystemTime::UNIX_EPOCH.into()
 }
 
 mod date_format {
     // copied from serde documentation on custom date (de)serializer
-    use chrono::{DateTime, TimeZone, Utc};
+    use chrono::{DateTime, Utc};
     use serde::{self, Deserialize, Deserializer, Serializer};
 
+    use crate::descriptor::nom_combinators::{all_consuming, date_flexible};
+
     const FORMAT: &str = "%Y-%m-%d %H:%M";
 
     pub fn serialize<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
@@ -198,8 +505,11 @@ mod date_format {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        Utc.datetime_from_str(&s, FORMAT)
-            .map_err(serde::de::Error::custom)
+        // indexes don't have seconds, but accept them in case they ever get more precise
+        let date = all_consuming(date_flexible)(&s)
+            .map(|(_, date)| date)
+            .map_err(|e| serde::de::Error::custom(format!("invalid date {s:?}: {e}")));
+        date
     }
 }
 
@@ -230,3 +540,277 @@ mod base64 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn file(path: &str, ttype: Type, first: DateTime<Utc>, last: DateTime<Utc>) -> File {
+        File {
+            path: path.to_owned(),
+            size: 0,
+            last_modified: last,
+            types: vec![VersionnedType {
+                ttype,
+                version: (1, 0),
+            }],
+            first_published: first,
+            last_published: last,
+            sha256: [0; 32],
+        }
+    }
+
+    fn test_index() -> Index {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        Index {
+            files: [
+                file("a", Type::BridgeExtraInfo, day(2), day(4)),
+                file("b", Type::BridgeExtraInfo, day(1), day(3)),
+                file("c", Type::BridgeExtraInfo, day(5), day(6)),
+                file("d", Type::BridgePoolAssignment, day(10), day(20)),
+            ]
+            .into_iter()
+            .collect(),
+            ..Index::default()
+        }
+    }
+
+    #[test]
+    fn test_files_in_range() {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        let index = test_index();
+
+        let paths: Vec<_> = index
+            .files_in_range(&Type::BridgeExtraInfo, &(day(3)..day(5)))
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(paths, ["b", "a"]);
+        assert_eq!(index.file_count_in_range(&Type::BridgeExtraInfo, &(..)), 3);
+        assert_eq!(
+            index.file_count_in_range(&Type::BridgePoolAssignment, &(..day(10))),
+            0
+        );
+    }
+
+    #[test]
+    fn test_overlap() {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        let f = file("a", Type::BridgeExtraInfo, day(10), day(20));
+
+        assert!(f.overlap(&(..)));
+
+        assert!(f.overlap(&(..=day(10))));
+        assert!(!f.overlap(&(..day(10))));
+        assert!(f.overlap(&(..=day(25))));
+        assert!(!f.overlap(&(..=day(5))));
+
+        assert!(f.overlap(&(day(20)..)));
+        assert!(f.overlap(&(day(5)..)));
+        assert!(!f.overlap(&(day(21)..)));
+
+        assert!(f.overlap(&(day(12)..=day(15))));
+        assert!(f.overlap(&(day(5)..=day(25))));
+        assert!(f.overlap(&(day(5)..=day(10))));
+        assert!(!f.overlap(&(day(5)..day(10))));
+        assert!(!f.overlap(&(day(21)..=day(25))));
+    }
+
+    #[test]
+    fn test_date_range() {
+        let index = test_index();
+        assert_eq!(index.oldest_file(&Type::BridgeExtraInfo).unwrap().path, "b");
+        assert_eq!(index.newest_file(&Type::BridgeExtraInfo).unwrap().path, "c");
+        assert_eq!(
+            index.date_range(&Type::BridgeExtraInfo),
+            Some(Utc.ymd(2022, 1, 1).and_hms(0, 0, 0)..=Utc.ymd(2022, 1, 6).and_hms(0, 0, 0))
+        );
+        assert_eq!(index.date_range(&Type::ServerDescriptor), None);
+    }
+
+    #[test]
+    fn test_files_by_month() {
+        let mut index = test_index();
+        index.files.insert(file(
+            "e",
+            Type::BridgeExtraInfo,
+            Utc.ymd(2021, 12, 31).and_hms(23, 0, 0),
+            Utc.ymd(2022, 1, 1).and_hms(1, 0, 0),
+        ));
+        index.files.insert(file(
+            "f",
+            Type::BridgeExtraInfo,
+            Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            Utc.ymd(2022, 3, 2).and_hms(0, 0, 0),
+        ));
+
+        let months = index.files_by_month(&Type::BridgeExtraInfo);
+        let paths: Vec<_> = months
+            .iter()
+            .map(|(month, files)| {
+                let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+                (*month, paths)
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                ((2021, 12), vec!["e"]),
+                ((2022, 1), vec!["b", "a", "c"]),
+                ((2022, 3), vec!["f"]),
+            ]
+        );
+        assert_eq!(
+            index.months_covered(&Type::BridgeExtraInfo),
+            [(2021, 12), (2022, 1), (2022, 3)]
+        );
+        assert_eq!(
+            index.months_covered(&Type::BridgePoolAssignment),
+            [(2022, 1)]
+        );
+        assert!(index.months_covered(&Type::ServerDescriptor).is_empty());
+    }
+
+    #[test]
+    fn test_to_vec() {
+        let mut index = test_index();
+        index.path = "https://collector.torproject.org".to_owned();
+        index.creation_time = Utc.ymd(2022, 2, 1).and_hms(12, 30, 0);
+        for file in std::mem::take(&mut index.files) {
+            let path = format!("recent/bridge-descriptors/{}", file.path);
+            index.files.insert(File { path, ..file });
+        }
+        index.files.insert(file(
+            "at-root",
+            Type::BridgeExtraInfo,
+            Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+            Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+        ));
+
+        let json = index.to_vec().unwrap();
+        assert_eq!(Index::from_slice(&json).unwrap(), index);
+    }
+
+    #[test]
+    fn test_file_order() {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        let mut index = test_index();
+        index
+            .files
+            .insert(file("e", Type::BridgeExtraInfo, day(1), day(2)));
+        index
+            .files
+            .insert(file("0", Type::BridgeExtraInfo, day(1), day(2)));
+
+        let paths: Vec<_> = index.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["0", "e", "b", "a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_files_equal() {
+        let index = test_index();
+        let mut rebuilt = test_index();
+        rebuilt.creation_time = Utc::now();
+        assert_ne!(index, rebuilt);
+        assert!(index.files_equal(&rebuilt));
+        assert!(index.same_files_as(&rebuilt));
+
+        rebuilt.path = "https://elsewhere".to_owned();
+        assert!(!index.files_equal(&rebuilt));
+        assert!(index.same_files_as(&rebuilt));
+
+        rebuilt.files.pop_first();
+        assert!(!index.same_files_as(&rebuilt));
+    }
+
+    #[test]
+    fn test_merge() {
+        let day = |d| Utc.ymd(2022, 1, d).and_hms(0, 0, 0);
+        let a = Index {
+            path: "https://a".to_owned(),
+            ..test_index()
+        };
+        let mut updated = file("a", Type::BridgeExtraInfo, day(2), day(5));
+        updated.sha256 = [1; 32];
+        let mut outdated = file("b", Type::BridgeExtraInfo, day(1), day(2));
+        outdated.sha256 = [1; 32];
+        let b = Index {
+            path: "https://b".to_owned(),
+            files: [
+                updated.clone(),
+                outdated,
+                file("e", Type::BridgeExtraInfo, day(7), day(8)),
+            ]
+            .into_iter()
+            .collect(),
+            ..Index::default()
+        };
+
+        let merged = Index::merge(a, b);
+        assert_eq!(merged.path, "https://a");
+        assert_eq!(merged.files.len(), 5);
+        let get = |path| merged.files.iter().find(|f| f.path == path).unwrap();
+        assert_eq!(get("a"), &updated);
+        assert_eq!(get("b").sha256, [0; 32]);
+        assert!(merged.files.iter().any(|f| f.path == "e"));
+    }
+
+    #[test]
+    fn test_archive_month() {
+        assert_eq!(
+            archive_month(
+                "archive/bridge-descriptors/extra-infos/bridge-extra-infos-2022-12.tar.xz"
+            ),
+            Some((
+                Utc.ymd(2022, 12, 1).and_hms(0, 0, 0),
+                Utc.ymd(2022, 12, 31).and_hms(23, 59, 59)
+            ))
+        );
+        assert_eq!(
+            archive_month("recent/2022-07-17-18-07-19-extra-infos"),
+            None
+        );
+        assert_eq!(archive_month("archive/something.tar.xz"), None);
+    }
+
+    #[tokio::test]
+    async fn test_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let extra_infos = dir.path().join("recent/bridge-descriptors/extra-infos");
+        fs::create_dir_all(&extra_infos).await.unwrap();
+        fs::copy(
+            "tests/bridge_extra_info_test",
+            extra_infos.join("2022-07-17-18-07-19-extra-infos"),
+        )
+        .await
+        .unwrap();
+        fs::write(dir.path().join("index.json"), "{}")
+            .await
+            .unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a descriptor")
+            .await
+            .unwrap();
+
+        let index = Index::from_directory(dir.path()).await.unwrap();
+        assert_eq!(index.files.len(), 1);
+        assert!(
+            Index::from_directory_excluding(dir.path(), &[dir.path().join("recent")])
+                .await
+                .unwrap()
+                .files
+                .is_empty()
+        );
+        let file = index.files.iter().next().unwrap();
+        assert_eq!(
+            file.path,
+            "recent/bridge-descriptors/extra-infos/2022-07-17-18-07-19-extra-infos"
+        );
+        assert!(file.type_matches(&Type::BridgeExtraInfo));
+        assert_eq!(file.size, 2430);
+        assert_eq!(file.first_published, file.last_modified);
+
+        let content = fs::read("tests/bridge_extra_info_test").await.unwrap();
+        assert_eq!(file.sha256.as_slice(), Sha256::digest(content).as_slice());
+    }
+}
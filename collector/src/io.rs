@@ -0,0 +1,102 @@
+//! Filesystem helpers.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::error::{Error, ErrorKind};
+
+/// Write `data` to `path` so that `path` is never left partially written.
+///
+/// Content is first written to a `.tmp` file next to `path`, while its sha256 is computed. The
+/// temporary file is renamed to `path` only once everything was written, and, if
+/// `expected_sha256` is set, the hash matches. Otherwise it is removed, and `path` is left
+/// untouched.
+///
+/// Returns the sha256 of `data`.
+pub async fn atomic_write<R: AsyncRead + Unpin>(
+    path: &Path,
+    data: R,
+    expected_sha256: Option<&[u8; 32]>,
+) -> Result<[u8; 32], Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let tmp_path = tmp_path(path);
+    let res = write_and_hash(&tmp_path, data).await.and_then(|sha256| {
+        if expected_sha256.is_none_or(|expected| *expected == sha256) {
+            Ok(sha256)
+        } else {
+            Err(ErrorKind::HashMissmatch.into())
+        }
+    });
+    match res {
+        Ok(sha256) => {
+            fs::rename(&tmp_path, path).await?;
+            Ok(sha256)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path).await;
+            Err(e)
+        }
+    }
+}
+
+/// Get the path of the temporary file used while writing to `path`.
+pub(crate) fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+async fn write_and_hash<R: AsyncRead + Unpin>(path: &Path, mut data: R) -> Result<[u8; 32], Error> {
+    let mut file = fs::File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 256 * 1024];
+    loop {
+        let len = data.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+        file.write_all(&buf[..len]).await?;
+    }
+    file.flush().await?;
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_atomic_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub/file");
+        let sha256: [u8; 32] = Sha256::digest(b"content").into();
+
+        assert_eq!(
+            atomic_write(&path, &b"content"[..], Some(&sha256))
+                .await
+                .unwrap(),
+            sha256
+        );
+        assert_eq!(fs::read(&path).await.unwrap(), b"content");
+        assert_eq!(tmp_path(&path), dir.path().join("sub/file.tmp"));
+        assert!(fs::metadata(tmp_path(&path)).await.is_err());
+
+        // a mismatch leaves the previous content in place
+        let err = atomic_write(&path, &b"other"[..], Some(&sha256))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Collector(ErrorKind::HashMissmatch)));
+        assert_eq!(fs::read(&path).await.unwrap(), b"content");
+        assert!(fs::metadata(tmp_path(&path)).await.is_err());
+
+        atomic_write(&path, &b"other"[..], None).await.unwrap();
+        assert_eq!(fs::read(&path).await.unwrap(), b"other");
+    }
+}
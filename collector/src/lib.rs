@@ -1,9 +1,15 @@
+pub mod analysis;
 mod collector;
 pub mod descriptor;
+pub mod download_state;
 pub mod error;
 pub mod index;
+pub mod io;
+pub mod stream;
 
-pub use crate::collector::CollecTor;
+pub use crate::collector::{
+    download_file, stream_from_any, CollecTor, CollecTorBuilder, CollecTorConfig,
+};
 use index::Index;
 
 #[cfg(test)]
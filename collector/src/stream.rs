@@ -0,0 +1,118 @@
+//! Helpers to work on streams of descriptors.
+
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+
+use crate::descriptor::kind::*;
+use crate::descriptor::Descriptor;
+
+/// What typed adapters do with errors found in the stream they adapt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    /// Silently drop errors
+    Skip,
+    /// Pass errors through
+    Propagate,
+}
+
+/// Keep only descriptors of type `T` from `stream`, handling errors according to `on_error`.
+pub fn typed<T, S, E>(stream: S, on_error: OnError) -> impl Stream<Item = Result<T, E>>
+where
+    T: TryFrom<Descriptor>,
+    S: Stream<Item = Result<Descriptor, E>>,
+{
+    stream.filter_map(move |desc| {
+        future::ready(match desc {
+            Ok(desc) => T::try_from(desc).ok().map(Ok),
+            Err(e) => match on_error {
+                OnError::Skip => None,
+                OnError::Propagate => Some(Err(e)),
+            },
+        })
+    })
+}
+
+macro_rules! typed_adapters {
+    ($($name:ident => $ty:ident,)*) => {
+        $(
+            #[doc = concat!("Keep only [`", stringify!($ty), "`] descriptors from `stream`. See [`typed`].")]
+            pub fn $name<S, E>(stream: S, on_error: OnError) -> impl Stream<Item = Result<$ty, E>>
+            where
+                S: Stream<Item = Result<Descriptor, E>>,
+            {
+                typed(stream, on_error)
+            }
+        )*
+    };
+}
+
+typed_adapters! {
+    bridge_extra_infos => BridgeExtraInfo,
+    bridge_network_statuses => BridgeNetworkStatus,
+    bridge_pool_assignments => BridgePoolAssignment,
+    bridge_server_descriptors => BridgeServerDescriptor,
+    bridgestrap_stats => BridgestrapStats,
+    directories => Directory,
+    microdescriptors => Microdescriptor,
+    network_status_microdesc_consensuses_3 => NetworkStatusMicrodescConsensus3,
+    server_descriptors => ServerDescriptor,
+}
+
+/// Drop descriptors of hibernating bridges. Errors are passed through.
+pub fn filter_active<S, E>(stream: S) -> impl Stream<Item = Result<BridgeServerDescriptor, E>>
+where
+    S: Stream<Item = Result<BridgeServerDescriptor, E>>,
+{
+    stream.filter(|desc| future::ready(desc.as_ref().map(|desc| desc.is_active()).unwrap_or(true)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use futures::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_typed_adapters() {
+        let descriptors = || {
+            stream::iter([
+                Ok(Descriptor::BridgeServerDescriptor(Box::new(
+                    BridgeServerDescriptor::empty(Utc::now()),
+                ))),
+                Err(()),
+                Ok(Descriptor::BridgeExtraInfo(Box::new(
+                    BridgeExtraInfo::empty(Utc::now()),
+                ))),
+            ])
+        };
+
+        let res: Vec<_> = bridge_server_descriptors(descriptors(), OnError::Propagate)
+            .collect()
+            .await;
+        assert_eq!(res.len(), 2);
+        assert!(res[0].is_ok());
+        assert!(res[1].is_err());
+
+        let res: Vec<_> = bridge_extra_infos(descriptors(), OnError::Skip)
+            .collect()
+            .await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_filter_active() {
+        let active = BridgeServerDescriptor::empty(Utc::now());
+        let mut hibernating = BridgeServerDescriptor::empty(Utc::now());
+        hibernating.hibernating = true;
+
+        let res: Vec<Result<_, ()>> =
+            filter_active(stream::iter([Ok(active), Ok(hibernating), Err(())]))
+                .collect()
+                .await;
+        assert_eq!(res.len(), 2);
+        assert!(res[0].as_ref().unwrap().is_active());
+        assert!(res[1].is_err());
+    }
+}
@@ -4,6 +4,7 @@ use chrono::{DateTime, Duration, TimeZone, Utc};
 use futures::stream::StreamExt;
 
 use collector::descriptor::{kind::*, Type};
+use collector::stream::{self, OnError};
 use collector::CollecTor;
 
 #[tokio::main]
@@ -21,28 +22,32 @@ async fn main() {
         .unwrap();
     println!("Download successfull, processing");
 
-    let set: BTreeSet<_> =
-        Box::pin(collector.stream_descriptors(Type::BridgePoolAssignment, start_date..))
-            .map(|d| d.unwrap().bridge_pool_assignment().unwrap())
-            .collect()
-            .await;
+    let set: BTreeSet<_> = Box::pin(stream::bridge_pool_assignments(
+        collector.stream_descriptors(Type::BridgePoolAssignment, start_date..),
+        OnError::Propagate,
+    ))
+    .map(Result::unwrap)
+    .collect()
+    .await;
 
     let changes = list_changes(set);
     println!("len={}", changes.len());
 
-    let descriptors: HashMap<String, BTreeSet<_>> =
-        Box::pin(collector.stream_descriptors(Type::BridgeServerDescriptor, start_date..))
-            .map(|d| d.unwrap().bridge_server_descriptor().unwrap())
-            .fold(
-                HashMap::<String, BTreeSet<_>>::new(),
-                |mut data, desc| async {
-                    data.entry(desc.fingerprint.clone())
-                        .or_default()
-                        .insert(desc);
-                    data
-                },
-            )
-            .await;
+    let descriptors: HashMap<String, BTreeSet<_>> = Box::pin(stream::bridge_server_descriptors(
+        collector.stream_descriptors(Type::BridgeServerDescriptor, start_date..),
+        OnError::Propagate,
+    ))
+    .map(Result::unwrap)
+    .fold(
+        HashMap::<String, BTreeSet<_>>::new(),
+        |mut data, desc| async {
+            data.entry(desc.fingerprint.clone())
+                .or_default()
+                .insert(desc);
+            data
+        },
+    )
+    .await;
 
     println!("finished building desc list");
 
@@ -101,7 +106,7 @@ fn list_changes(descs: BTreeSet<BridgePoolAssignment>) -> Vec<Change> {
             if let Some(v2) = new_desc.data.get(k) {
                 if v1.0 != v2.0 {
                     res.push(Change {
-                        fingerprint: k.to_ascii_uppercase(),
+                        fingerprint: k.to_string().to_ascii_uppercase(),
                         before: previous_desc.timestamp,
                         after: new_desc.timestamp,
                         old_mechanism: v1.0.clone(),
@@ -4,6 +4,7 @@ use chrono::{Date, DateTime, TimeZone, Utc};
 use futures::stream::StreamExt;
 
 use collector::descriptor::Type;
+use collector::stream::{self, OnError};
 use collector::CollecTor;
 
 #[tokio::main]
@@ -20,15 +21,17 @@ async fn main() {
         .unwrap();
     eprintln!("Download successfull, processing");
 
-    let res = collector
-        .stream_descriptors(Type::BridgePoolAssignment, start_date..)
-        .map(|d| d.unwrap().bridge_pool_assignment().unwrap())
-        .for_each(|bpa| async move {
-            for (fp, (assign, _meta)) in bpa.data {
-                if fp == "4d6e3ca2110fc36d3106c86940a1d4c8c91923ab" {
-                    println!("assign={assign}")
-                }
+    let res = stream::bridge_pool_assignments(
+        collector.stream_descriptors(Type::BridgePoolAssignment, start_date..),
+        OnError::Propagate,
+    )
+    .map(Result::unwrap)
+    .for_each(|bpa| async move {
+        for (fp, assign, _meta) in bpa.iter() {
+            if fp.to_string() == "4d6e3ca2110fc36d3106c86940a1d4c8c91923ab" {
+                println!("assign={assign}")
             }
-        })
-        .await;
+        }
+    })
+    .await;
 }